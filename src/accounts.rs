@@ -10,6 +10,10 @@ use dashmap::DashMap;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
 use thiserror::Error;
+use tokio::sync::broadcast;
+
+/// How many account-change notifications can be buffered for a slow subscriber before it lags
+const ACCOUNT_UPDATES_CAPACITY: usize = 4096;
 
 pub type AccountData = Vec<u8>;
 
@@ -31,17 +35,40 @@ pub trait AccountsGetter: Send + Sync {
     async fn add_or_update_account(&self, update: AccountUpdate);
 
     async fn get_account(&self, key: &Pubkey) -> Result<AccountData, AccountsError>;
+
+    /// Subscribes to the keys of accounts whose data changes. Stores that don't track changes
+    /// return `None`.
+    fn subscribe_updates(&self) -> Option<broadcast::Receiver<Pubkey>> {
+        None
+    }
 }
 
-#[derive(Default)]
 pub struct MemStore {
     accounts_map: Arc<DashMap<Pubkey, AccountData>>,
+    updates: broadcast::Sender<Pubkey>,
+}
+
+impl Default for MemStore {
+    fn default() -> Self {
+        let (updates, _) = broadcast::channel(ACCOUNT_UPDATES_CAPACITY);
+        MemStore {
+            accounts_map: Arc::default(),
+            updates,
+        }
+    }
 }
 
 #[async_trait]
 impl AccountsGetter for MemStore {
     async fn add_or_update_account(&self, update: AccountUpdate) {
-        let _ = self.accounts_map.insert(update.pubkey, update.data);
+        let AccountUpdate { pubkey, data } = update;
+        // Polling stores re-write unchanged accounts, which subscribers shouldn't hear about
+        let changed = self.accounts_map.get(&pubkey).map_or(true, |v| *v != data);
+        let _ = self.accounts_map.insert(pubkey, data);
+        if changed {
+            // Only fails when nobody is subscribed
+            let _ = self.updates.send(pubkey);
+        }
     }
 
     async fn get_account(&self, key: &Pubkey) -> Result<AccountData, AccountsError> {
@@ -51,6 +78,10 @@ impl AccountsGetter for MemStore {
             .map(|v| v.clone())
             .ok_or(AccountsError::NotFound)?)
     }
+
+    fn subscribe_updates(&self) -> Option<broadcast::Receiver<Pubkey>> {
+        Some(self.updates.subscribe())
+    }
 }
 
 pub struct SolanaRpcStore {
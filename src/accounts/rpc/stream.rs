@@ -104,4 +104,8 @@ impl AccountsGetter for RpcAccounts {
     async fn get_account(&self, key: &Pubkey) -> Result<AccountData, AccountsError> {
         self.store.get_account(key).await
     }
+
+    fn subscribe_updates(&self) -> Option<tokio::sync::broadcast::Receiver<Pubkey>> {
+        self.store.subscribe_updates()
+    }
 }
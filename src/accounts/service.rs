@@ -1,10 +1,11 @@
 use super::{AccountsError, AccountsGetter};
 use crate::accounts::{rpc, AccountData, AccountUpdate, PoolSlice};
+use crate::gfx_swap::transfer_hook::get_extra_account_metas_address;
 use crate::utils::get_keys_for_pool_exclusive;
-use std::collections::HashSet;
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use dashmap::DashSet;
 use futures::{Stream, StreamExt};
 use log::error;
 use solana_client::nonblocking::rpc_client::RpcClient;
@@ -18,7 +19,7 @@ pub async fn bootstrap_accounts_service(
     program_id: Pubkey,
     config: Pubkey,
 ) -> anyhow::Result<(JoinHandle<Result<(), anyhow::Error>>, AccountsService)> {
-    let mut processed_pools = HashSet::<Pubkey>::new();
+    let processed_pools = Arc::new(DashSet::<Pubkey>::new());
     let mut pool_keys = rpc::get_amm_pool_pubkeys(&rpc_client, &config, &program_id).await?;
     log::debug!(
         "Bootstrapping accounts service with data for {} pools",
@@ -48,7 +49,7 @@ pub async fn bootstrap_accounts_service(
         };
 
         process_amm_pool(
-            &mut processed_pools,
+            &processed_pools,
             Arc::clone(&accounts_store),
             &rpc_client,
             &program_id,
@@ -60,12 +61,12 @@ pub async fn bootstrap_accounts_service(
 
     let task = tokio::task::spawn({
         let rpc_client = Arc::clone(&rpc_client);
-        let mut pool_set = processed_pools;
+        let pool_set = Arc::clone(&processed_pools);
         let accounts_store = Arc::clone(&accounts_store);
         async move {
             while let Some((pool, pool_data)) = amm_pools.next().await {
                 process_amm_pool(
-                    &mut pool_set,
+                    &pool_set,
                     Arc::clone(&accounts_store),
                     &rpc_client,
                     &program_id,
@@ -78,13 +79,16 @@ pub async fn bootstrap_accounts_service(
         }
     });
 
-    let service = AccountsService { accounts_store };
+    let service = AccountsService {
+        accounts_store,
+        pools: processed_pools,
+    };
 
     Ok((task, service))
 }
 
 async fn process_amm_pool(
-    processed_pools: &mut HashSet<Pubkey>,
+    processed_pools: &DashSet<Pubkey>,
     accounts_store: Arc<dyn AccountsGetter>,
     rpc_client: &RpcClient,
     program_id: &Pubkey,
@@ -112,8 +116,9 @@ async fn process_amm_pool(
             data: pool_data,
         })
         .await;
+    let mut hook_validation_keys = vec![];
     for (pubkey, account) in accounts.into_iter() {
-        let Some(data) = account else {
+        let Some(account_data) = account else {
             // this should be unreachable
             error!(
                 "Got null account data from RPC. pool={}. account={}",
@@ -121,16 +126,58 @@ async fn process_amm_pool(
             );
             continue;
         };
+        if pubkey == data.token_0_mint || pubkey == data.token_1_mint {
+            hook_validation_keys.extend(get_extra_account_metas_address(&pubkey, &account_data));
+        }
         accounts_store
-            .add_or_update_account(AccountUpdate { pubkey, data })
+            .add_or_update_account(AccountUpdate {
+                pubkey,
+                data: account_data,
+            })
             .await;
     }
+
+    // Transfer-hook mints need their hook's `ExtraAccountMetaList` to build swaps
+    if !hook_validation_keys.is_empty() {
+        match rpc::get_multiple_account_data(rpc_client, &hook_validation_keys).await {
+            Ok(accounts) => {
+                for (pubkey, account) in accounts {
+                    let Some(data) = account else {
+                        error!(
+                            "Transfer-hook validation account {} not found. pool={}",
+                            pubkey, pool
+                        );
+                        continue;
+                    };
+                    accounts_store
+                        .add_or_update_account(AccountUpdate { pubkey, data })
+                        .await;
+                }
+            }
+            Err(e) => error!(
+                "Failed to fetch transfer-hook accounts for amm pool {}: {}",
+                pool, e
+            ),
+        }
+    }
     processed_pools.insert(pool);
 }
 
 #[derive(Clone)]
 pub struct AccountsService {
     accounts_store: Arc<dyn AccountsGetter>,
+    pools: Arc<DashSet<Pubkey>>,
+}
+
+impl AccountsService {
+    /// Pools whose accounts are being tracked
+    pub fn pools(&self) -> Vec<Pubkey> {
+        self.pools.iter().map(|pool| *pool.key()).collect()
+    }
+
+    pub fn is_pool(&self, key: &Pubkey) -> bool {
+        self.pools.contains(key)
+    }
 }
 
 #[async_trait]
@@ -140,4 +187,8 @@ impl AccountsGetter for AccountsService {
     async fn get_account(&self, key: &Pubkey) -> Result<AccountData, AccountsError> {
         self.accounts_store.get_account(key).await
     }
+
+    fn subscribe_updates(&self) -> Option<tokio::sync::broadcast::Receiver<Pubkey>> {
+        self.accounts_store.subscribe_updates()
+    }
 }
@@ -0,0 +1,116 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{Json, State},
+    routing::{get, post},
+    Router,
+};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use clap::Parser;
+use gamma_swap_api::jito::MAINNET_TIP_ACCOUNTS;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::transaction::VersionedTransaction;
+
+/// Most transactions a bundle can hold
+const MAX_BUNDLE_TRANSACTIONS: usize = 5;
+
+/// A stand-in for a Jito block engine and its tip floor endpoint, for exercising tips and
+/// `/execute` without landing anything. Bundles are checked and logged, never forwarded.
+#[derive(Parser)]
+pub struct Config {
+    /// The port to serve the mock block engine on
+    #[clap(long, env = "MOCK_BLOCK_ENGINE_PORT", default_value = "9090")]
+    port: u16,
+    /// The median landed tip to report from the tip floor endpoint, in lamports
+    #[clap(long, env = "MOCK_TIP_FLOOR_LAMPORTS", default_value = "10000")]
+    tip_floor_lamports: u64,
+}
+
+#[tokio::main]
+pub async fn main() -> anyhow::Result<()> {
+    dotenv::dotenv().ok();
+    env_logger::init();
+
+    let opts = Config::parse();
+    let app = Router::new()
+        .route("/api/v1/bundles", post(bundles))
+        .route("/api/v1/bundles/tip_floor", get(tip_floor))
+        .with_state(opts.tip_floor_lamports);
+
+    let socket_addr = SocketAddr::from(([127, 0, 0, 1], opts.port));
+    log::info!("Mock block engine running on {}", socket_addr);
+    axum::Server::bind(&socket_addr)
+        .serve(app.into_make_service())
+        .await?;
+
+    Ok(())
+}
+
+async fn bundles(Json(request): Json<Value>) -> Json<Value> {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let result = match request.get("method").and_then(Value::as_str) {
+        Some("getTipAccounts") => Ok(json!(MAINNET_TIP_ACCOUNTS
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>())),
+        Some("sendBundle") => send_bundle(&request["params"]).map(Value::String),
+        method => Err(format!("Method not found: {method:?}")),
+    };
+    Json(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(message) => {
+            log::warn!("Rejected request: {}", message);
+            json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32602, "message": message } })
+        }
+    })
+}
+
+/// Checks the bundle decodes and is signed. Its ID is a hash of the transaction signatures.
+fn send_bundle(params: &Value) -> Result<String, String> {
+    let encoded = params[0]
+        .as_array()
+        .ok_or("Expected a list of transactions")?;
+    if encoded.is_empty() || encoded.len() > MAX_BUNDLE_TRANSACTIONS {
+        return Err(format!(
+            "Bundles hold 1 to {} transactions",
+            MAX_BUNDLE_TRANSACTIONS
+        ));
+    }
+    if params[1]["encoding"].as_str() != Some("base64") {
+        return Err("Only base64 encoding is supported".to_string());
+    }
+
+    let mut hasher = Sha256::new();
+    for encoded in encoded {
+        let bytes = STANDARD
+            .decode(encoded.as_str().ok_or("Expected a base64 string")?)
+            .map_err(|e| e.to_string())?;
+        let transaction: VersionedTransaction =
+            bincode::deserialize(&bytes).map_err(|e| e.to_string())?;
+        transaction.sanitize().map_err(|e| e.to_string())?;
+        transaction
+            .verify_and_hash_message()
+            .map_err(|e| e.to_string())?;
+        log::info!("Bundled transaction {}", transaction.signatures[0]);
+        hasher.update(transaction.signatures[0].as_ref());
+    }
+    let bundle_id = hex::encode(hasher.finalize());
+    log::info!("Accepted bundle {}", bundle_id);
+    Ok(bundle_id)
+}
+
+async fn tip_floor(State(tip_floor_lamports): State<u64>) -> Json<Value> {
+    let tip = tip_floor_lamports as f64 / LAMPORTS_PER_SOL as f64;
+    Json(json!([{
+        "time": "1970-01-01T00:00:00Z",
+        "landed_tips_25th_percentile": tip / 2.0,
+        "landed_tips_50th_percentile": tip,
+        "landed_tips_75th_percentile": tip * 2.0,
+        "landed_tips_95th_percentile": tip * 4.0,
+        "landed_tips_99th_percentile": tip * 8.0,
+        "ema_landed_tips_50th_percentile": tip,
+    }]))
+}
@@ -0,0 +1,160 @@
+use anyhow::anyhow;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use clap::{Parser, Subcommand};
+use serde_json::{json, Value};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{EncodableKey, Keypair, Signer};
+use solana_sdk::transaction::VersionedTransaction;
+
+/// Sets up referral accounts through the swap API's referral endpoints. Transactions are signed
+/// and sent with the keypair, or printed unsigned with `--dry-run`.
+#[derive(Parser)]
+pub struct Config {
+    /// The host of the api server to connect to
+    #[clap(long, env = "HOST")]
+    server_host: String,
+    /// The port of the api server to connect to
+    #[clap(long, env = "PORT")]
+    server_port: String,
+    /// Keypair that pays for and signs the transactions
+    #[clap(long, env, default_value = "keypair.json")]
+    keypair_path: String,
+    /// Print the unsigned transactions instead of sending them
+    #[clap(long)]
+    dry_run: bool,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a named referral account
+    InitializeAccount {
+        /// The referral account's name, which its address is derived from
+        #[clap(long)]
+        name: String,
+        /// Receives the referral's share of fees. Defaults to the keypair.
+        #[clap(long)]
+        partner: Option<Pubkey>,
+        /// The project to create the account under. Defaults to the server's.
+        #[clap(long)]
+        project: Option<Pubkey>,
+    },
+    /// Create the referral token accounts missing for mints traded in Gamma pools
+    InitializeTokenAccounts {
+        #[clap(long, env)]
+        referral_account: Pubkey,
+    },
+    /// Show the fees a referral account has accrued per mint
+    Fees {
+        #[clap(long, env)]
+        referral_account: Pubkey,
+    },
+}
+
+#[tokio::main]
+pub async fn main() -> anyhow::Result<()> {
+    dotenv::dotenv()?;
+    env_logger::init();
+
+    let opts = Config::parse();
+    let base_path = format!("http://{}:{}", opts.server_host, opts.server_port);
+    log::info!("Base path: {}", base_path);
+    let client = reqwest::Client::new();
+
+    let transactions = match &opts.command {
+        Command::InitializeAccount {
+            name,
+            partner,
+            project,
+        } => {
+            let payer = read_keypair(&opts.keypair_path)?.pubkey();
+            let mut request = json!({ "payer": payer.to_string(), "name": name });
+            if let Some(partner) = partner {
+                request["partner"] = json!(partner.to_string());
+            }
+            if let Some(project) = project {
+                request["project"] = json!(project.to_string());
+            }
+            let response = post(
+                &client,
+                &format!("{base_path}/referral/initialize-account"),
+                request,
+            )
+            .await?;
+            log::info!("Referral account: {}", response["referralAccount"]);
+            vec![response["transaction"].clone()]
+        }
+        Command::InitializeTokenAccounts { referral_account } => {
+            let payer = read_keypair(&opts.keypair_path)?.pubkey();
+            let response = post(
+                &client,
+                &format!("{base_path}/referral/initialize-token-accounts"),
+                json!({
+                    "payer": payer.to_string(),
+                    "referralAccount": referral_account.to_string(),
+                }),
+            )
+            .await?;
+            log::info!("Missing token accounts for mints: {}", response["mints"]);
+            response["transactions"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default()
+        }
+        Command::Fees { referral_account } => {
+            let response = client
+                .get(format!("{base_path}/referral/{referral_account}/fees"))
+                .send()
+                .await?
+                .json::<Value>()
+                .await?;
+            println!("{}", serde_json::to_string_pretty(&response)?);
+            return Ok(());
+        }
+    };
+
+    if opts.dry_run {
+        for transaction in transactions {
+            println!("{}", transaction.as_str().unwrap_or_default());
+        }
+        return Ok(());
+    }
+
+    let keypair = read_keypair(&opts.keypair_path)?;
+    let rpc_client = RpcClient::new(std::env::var("RPC_URL")?);
+    for transaction in transactions {
+        let bytes = STANDARD.decode(
+            transaction
+                .as_str()
+                .ok_or_else(|| anyhow!("Unexpected response: {}", transaction))?,
+        )?;
+        let transaction = bincode::deserialize::<VersionedTransaction>(&bytes)?;
+        let transaction = VersionedTransaction::try_new(transaction.message, &[&keypair])?;
+        let signature = rpc_client
+            .send_and_confirm_transaction_with_spinner(&transaction)
+            .await?;
+        println!(
+            "View confirmed txn at: https://explorer.solana.com/tx/{}",
+            signature
+        );
+    }
+
+    Ok(())
+}
+
+fn read_keypair(path: &str) -> anyhow::Result<Keypair> {
+    Keypair::read_from_file(path).map_err(|e| anyhow!("Failed to read keypair {}: {}", path, e))
+}
+
+async fn post(client: &reqwest::Client, url: &str, body: Value) -> anyhow::Result<Value> {
+    let response = client.post(url).json(&body).send().await?;
+    let status = response.status();
+    let body = response.json::<Value>().await?;
+    if !status.is_success() {
+        return Err(anyhow!("{}: {}", status, body["message"]));
+    }
+    Ok(body)
+}
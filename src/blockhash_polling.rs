@@ -1,18 +1,106 @@
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::anyhow;
+use futures::StreamExt;
 use log::warn;
+use serde::Serialize;
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::clock::MAX_PROCESSING_AGE;
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::hash::Hash;
 use tokio::sync::RwLock;
+use yellowstone_grpc_proto::geyser::{
+    subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest,
+    SubscribeRequestFilterBlocksMeta,
+};
+
+use crate::accounts::grpc;
 
 const DEFAULT_REFRESH_RATE: Duration = Duration::from_secs(5);
+/// Failed polls in a row after which the cached blockhash is reported as stale
+const STALE_AFTER_FAILURES: u32 = 3;
+/// Delay before reconnecting a dropped blockhash stream
+const GRPC_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Where the cached blockhash came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BlockhashSource {
+    /// `getLatestBlockhash` polling
+    Rpc,
+    /// A yellowstone `blocks_meta` subscription
+    Grpc,
+}
 
 pub struct RecentBlockhash {
     pub hash: Hash,
     pub last_valid_block_height: u64,
+    pub updated_at: Instant,
+    pub source: BlockhashSource,
+    /// Failures since the blockhash was last updated
+    pub consecutive_failures: u32,
+    pub metrics: BlockhashMetrics,
+}
+
+/// Running totals since startup
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockhashMetrics {
+    pub rpc_updates: u64,
+    pub rpc_failures: u64,
+    pub grpc_updates: u64,
+    /// Failed connections, subscriptions and dropped streams
+    pub grpc_failures: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockhashReport {
+    pub blockhash: String,
+    pub last_valid_block_height: u64,
+    /// Milliseconds since the blockhash was fetched
+    pub age_ms: u64,
+    pub source: BlockhashSource,
+    pub consecutive_failures: u32,
+    /// Whether polling has been failing, so the blockhash may expire sooner than its age suggests
+    pub stale: bool,
+    pub metrics: BlockhashMetrics,
+}
+
+impl RecentBlockhash {
+    pub fn report(&self) -> BlockhashReport {
+        BlockhashReport {
+            blockhash: self.hash.to_string(),
+            last_valid_block_height: self.last_valid_block_height,
+            age_ms: u64::try_from(self.updated_at.elapsed().as_millis()).unwrap_or(u64::MAX),
+            source: self.source,
+            consecutive_failures: self.consecutive_failures,
+            stale: self.consecutive_failures >= STALE_AFTER_FAILURES,
+            metrics: self.metrics,
+        }
+    }
+
+    fn update(&mut self, hash: Hash, last_valid_block_height: u64, source: BlockhashSource) {
+        self.hash = hash;
+        self.last_valid_block_height = last_valid_block_height;
+        self.updated_at = Instant::now();
+        self.source = source;
+        self.consecutive_failures = 0;
+        match source {
+            BlockhashSource::Rpc => self.metrics.rpc_updates += 1,
+            BlockhashSource::Grpc => self.metrics.grpc_updates += 1,
+        }
+    }
+
+    fn record_failure(&mut self, source: BlockhashSource) {
+        self.consecutive_failures += 1;
+        match source {
+            BlockhashSource::Rpc => self.metrics.rpc_failures += 1,
+            BlockhashSource::Grpc => self.metrics.grpc_failures += 1,
+        }
+    }
 }
 
 pub async fn get_blockhash_data_with_retry(
@@ -29,6 +117,10 @@ pub async fn get_blockhash_data_with_retry(
                 let update = RecentBlockhash {
                     hash,
                     last_valid_block_height,
+                    updated_at: Instant::now(),
+                    source: BlockhashSource::Rpc,
+                    consecutive_failures: 0,
+                    metrics: BlockhashMetrics::default(),
                 };
                 return Ok(update);
             }
@@ -42,25 +134,119 @@ pub async fn get_blockhash_data_with_retry(
     ))
 }
 
+/// Polls for the latest blockhash. As a `fallback`, a poll is only made when nothing else has
+/// updated the blockhash within the poll rate.
 pub fn start_blockhash_polling_task(
     rpc_client: Arc<RpcClient>,
     blockhash_notif: Arc<RwLock<RecentBlockhash>>,
     commitment_config: CommitmentConfig,
     poll_rate: Option<Duration>,
+    fallback: bool,
 ) -> tokio::task::JoinHandle<anyhow::Result<()>> {
+    let poll_rate = poll_rate.unwrap_or(DEFAULT_REFRESH_RATE);
     tokio::spawn(async move {
         loop {
-            if let Ok((hash, last_valid_block_height)) = rpc_client
+            tokio::time::sleep(poll_rate).await;
+            if fallback && blockhash_notif.read().await.updated_at.elapsed() < poll_rate {
+                continue;
+            }
+
+            match rpc_client
                 .get_latest_blockhash_with_commitment(commitment_config)
                 .await
             {
-                *blockhash_notif.write().await = RecentBlockhash {
-                    hash,
-                    last_valid_block_height,
-                };
+                Ok((hash, last_valid_block_height)) => {
+                    blockhash_notif.write().await.update(
+                        hash,
+                        last_valid_block_height,
+                        BlockhashSource::Rpc,
+                    );
+                }
+                Err(e) => {
+                    let mut blockhash = blockhash_notif.write().await;
+                    blockhash.record_failure(BlockhashSource::Rpc);
+                    warn!(
+                        "Failed to poll blockhash ({} in a row): {}",
+                        blockhash.consecutive_failures, e
+                    );
+                }
             }
+        }
+    })
+}
 
-            tokio::time::sleep(poll_rate.unwrap_or(DEFAULT_REFRESH_RATE)).await;
+/// Streams blockhashes from confirmed blocks. Pair with a fallback polling task, which takes over
+/// while the stream is down.
+pub fn start_grpc_blockhash_task(
+    grpc_endpoint: String,
+    grpc_x_token: Option<String>,
+    blockhash_notif: Arc<RwLock<RecentBlockhash>>,
+) -> tokio::task::JoinHandle<anyhow::Result<()>> {
+    log::debug!("Starting GRPC blockhash task");
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = stream_blockhashes(
+                grpc_endpoint.clone(),
+                grpc_x_token.clone(),
+                &blockhash_notif,
+            )
+            .await
+            {
+                let mut blockhash = blockhash_notif.write().await;
+                blockhash.record_failure(BlockhashSource::Grpc);
+                warn!(
+                    "GRPC blockhash stream failed ({} in a row): {}. Reconnecting..",
+                    blockhash.consecutive_failures, e
+                );
+            }
+            tokio::time::sleep(GRPC_RECONNECT_DELAY).await;
         }
     })
 }
+
+async fn stream_blockhashes(
+    grpc_endpoint: String,
+    grpc_x_token: Option<String>,
+    blockhash_notif: &RwLock<RecentBlockhash>,
+) -> anyhow::Result<()> {
+    let mut client = grpc::create_grpc_connection(&grpc_endpoint, &grpc_x_token).await?;
+    let mut stream = client
+        .subscribe_once(SubscribeRequest {
+            blocks_meta: HashMap::from([(
+                "grpc_blockhash_subscription".to_string(),
+                SubscribeRequestFilterBlocksMeta::default(),
+            )]),
+            commitment: Some(CommitmentLevel::Confirmed.into()),
+            ..Default::default()
+        })
+        .await?;
+
+    while let Some(message) = stream.next().await {
+        let Some(update) = message?.update_oneof else {
+            continue;
+        };
+        match update {
+            UpdateOneof::BlockMeta(block_meta) => {
+                let Some(block_height) = block_meta.block_height else {
+                    continue;
+                };
+                let hash = block_meta.blockhash.parse::<Hash>()?;
+                // Same as `getLatestBlockhash`: a blockhash is valid for MAX_PROCESSING_AGE blocks
+                let last_valid_block_height = block_height.block_height + MAX_PROCESSING_AGE as u64;
+                let mut blockhash = blockhash_notif.write().await;
+                // Blocks can arrive out of order around reconnects
+                if last_valid_block_height >= blockhash.last_valid_block_height {
+                    blockhash.update(hash, last_valid_block_height, BlockhashSource::Grpc);
+                }
+            }
+            UpdateOneof::Ping(_) => {
+                log::trace!("Received ping from GRPC blockhash stream");
+            }
+            _ => {
+                log::error!("Received unexpected message from GRPC blockhash stream");
+            }
+        }
+    }
+
+    Err(anyhow!("stream ended"))
+}
@@ -0,0 +1,151 @@
+use super::GfxSwapClient;
+use crate::jito::{JitoClient, MIN_TIP_LAMPORTS};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction::SystemInstruction;
+use solana_sdk::transaction::VersionedTransaction;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ExecuteError {
+    #[error("Invalid transaction: {0}")]
+    InvalidTransaction(String),
+    #[error("Transaction doesn't tip a Jito tip account. Build it with `jitoTipLamports`")]
+    MissingTip,
+    #[error("Transaction doesn't call the Gamma program {0}")]
+    NotAGammaTransaction(Pubkey),
+    #[error("Block engine error: {0}")]
+    BlockEngine(anyhow::Error),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecuteRequest {
+    /// Base64 of a fully signed transaction, as returned by /swap and signed
+    pub signed_transaction: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecuteResponse {
+    pub bundle_id: String,
+    /// The transaction's signature, to follow it on-chain
+    pub signature: String,
+}
+
+impl GfxSwapClient {
+    /// Submits a signed swap through this server's block engine. See [`execute_bundle`].
+    pub async fn execute(&self, req: ExecuteRequest) -> Result<ExecuteResponse, ExecuteError> {
+        execute_bundle(&self.jito, &self.gamma_program_id, req).await
+    }
+}
+
+/// Submits a signed swap to the block engine as a single-transaction bundle. The swap has to
+/// tip one of the engine's tip accounts, or the bundle won't be considered, and has to call the
+/// Gamma program, so the server doesn't relay arbitrary transactions.
+pub async fn execute_bundle(
+    jito: &JitoClient,
+    gamma_program_id: &Pubkey,
+    req: ExecuteRequest,
+) -> Result<ExecuteResponse, ExecuteError> {
+    let bytes = STANDARD
+        .decode(&req.signed_transaction)
+        .map_err(|e| ExecuteError::InvalidTransaction(e.to_string()))?;
+    let transaction: VersionedTransaction = bincode::deserialize(&bytes)
+        .map_err(|e| ExecuteError::InvalidTransaction(e.to_string()))?;
+    transaction
+        .sanitize()
+        .map_err(|e| ExecuteError::InvalidTransaction(e.to_string()))?;
+    transaction
+        .verify_and_hash_message()
+        .map_err(|e| ExecuteError::InvalidTransaction(e.to_string()))?;
+    if !calls_program(&transaction, gamma_program_id) {
+        return Err(ExecuteError::NotAGammaTransaction(*gamma_program_id));
+    }
+    if !tips_jito(jito, &transaction).await {
+        return Err(ExecuteError::MissingTip);
+    }
+
+    let bundle_id = jito
+        .send_bundle(std::slice::from_ref(&transaction))
+        .await
+        .map_err(ExecuteError::BlockEngine)?;
+    log::info!(
+        "Submitted bundle {} for {}",
+        bundle_id,
+        transaction.signatures[0]
+    );
+    Ok(ExecuteResponse {
+        bundle_id,
+        signature: transaction.signatures[0].to_string(),
+    })
+}
+
+/// Whether the transaction transfers at least the minimum tip to a tip account
+async fn tips_jito(jito: &JitoClient, transaction: &VersionedTransaction) -> bool {
+    let account_keys = transaction.message.static_account_keys();
+    for instruction in transaction.message.instructions() {
+        if instruction.program_id(account_keys) != &solana_sdk::system_program::ID {
+            continue;
+        }
+        let Ok(SystemInstruction::Transfer { lamports }) =
+            bincode::deserialize::<SystemInstruction>(&instruction.data)
+        else {
+            continue;
+        };
+        let recipient = instruction
+            .accounts
+            .get(1)
+            .and_then(|index| account_keys.get(usize::from(*index)));
+        if let Some(recipient) = recipient {
+            if lamports >= MIN_TIP_LAMPORTS && jito.is_tip_account(recipient).await {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Whether any of the transaction's instructions is for `program_id`
+fn calls_program(transaction: &VersionedTransaction, program_id: &Pubkey) -> bool {
+    let account_keys = transaction.message.static_account_keys();
+    transaction
+        .message
+        .instructions()
+        .iter()
+        .any(|instruction| instruction.program_id(account_keys) == program_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::instruction::Instruction;
+    use solana_sdk::message::{Message, VersionedMessage};
+
+    fn transaction(instructions: &[Instruction]) -> VersionedTransaction {
+        let payer = Pubkey::new_unique();
+        VersionedTransaction {
+            signatures: vec![Default::default()],
+            message: VersionedMessage::Legacy(Message::new(instructions, Some(&payer))),
+        }
+    }
+
+    #[test]
+    fn finds_calls_to_the_program() {
+        let program_id = Pubkey::new_unique();
+        let tip = solana_sdk::system_instruction::transfer(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            MIN_TIP_LAMPORTS,
+        );
+        let swap = Instruction::new_with_bytes(program_id, &[1], vec![]);
+        assert!(calls_program(
+            &transaction(&[tip.clone(), swap]),
+            &program_id
+        ));
+        assert!(!calls_program(&transaction(&[tip]), &program_id));
+    }
+}
@@ -0,0 +1,338 @@
+use super::quote::{FeeBreakdown, PairSnapshot, QuoteError};
+use super::GfxSwapClient;
+
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use swap_api::quote::SwapMode;
+use swap_api::serde_helpers::field_as_string;
+
+/// Upper bound on the number of sizes evaluated for one ladder
+const MAX_LADDER_STEPS: usize = 100;
+/// Number of log-spaced sizes used when the request doesn't specify one
+const DEFAULT_LADDER_STEPS: u16 = 20;
+/// The max-input search never goes beyond this multiple of the input reserve
+const MAX_INPUT_SEARCH_RESERVE_MULTIPLE: u64 = 100;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LadderRequest {
+    #[serde(with = "field_as_string")]
+    pub input_mint: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub output_mint: Pubkey,
+    pub swap_mode: Option<SwapMode>,
+    /// Comma-separated list of sizes to quote
+    pub amounts: Option<String>,
+    /// Smallest size of a log-spaced ladder. Used when `amounts` isn't specified
+    pub min_amount: Option<u64>,
+    /// Largest size of a log-spaced ladder. Used when `amounts` isn't specified
+    pub max_amount: Option<u64>,
+    /// Number of log-spaced sizes between `min_amount` and `max_amount`
+    pub steps: Option<u16>,
+    /// If set, report the largest input whose price impact stays at or below this percentage
+    pub max_price_impact_pct: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuoteLadder {
+    #[serde(with = "field_as_string")]
+    pub input_mint: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub output_mint: Pubkey,
+    pub swap_mode: SwapMode,
+    pub context_slot: u64,
+    /// One entry per size, in ascending order. Sizes beyond the pool's liquidity are left out.
+    pub steps: Vec<LadderStep>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_input_within_impact: Option<ImpactLimit>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LadderStep {
+    #[serde(with = "field_as_string")]
+    pub in_amount: u64,
+    #[serde(with = "field_as_string")]
+    pub out_amount: u64,
+    /// Output tokens received per input token, in raw amounts
+    pub effective_price: String,
+    pub price_impact_pct: String,
+    pub fee_breakdown: FeeBreakdown,
+}
+
+/// The largest ExactIn size that keeps price impact under the requested threshold
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImpactLimit {
+    pub max_price_impact_pct: String,
+    #[serde(with = "field_as_string")]
+    pub in_amount: u64,
+    #[serde(with = "field_as_string")]
+    pub out_amount: u64,
+    pub price_impact_pct: String,
+}
+
+impl LadderRequest {
+    /// The sizes to evaluate, sorted and deduplicated
+    fn sizes(&self) -> Result<Vec<u64>, QuoteError> {
+        let mut sizes = match (&self.amounts, self.min_amount, self.max_amount) {
+            (Some(amounts), _, _) => amounts
+                .split(',')
+                .map(|amount| {
+                    amount.trim().parse::<u64>().map_err(|_| {
+                        QuoteError::InvalidRequest(format!("Invalid amount in ladder: {}", amount))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            (None, Some(min), Some(max)) if min > 0 && min <= max => {
+                let steps = self.steps.unwrap_or(DEFAULT_LADDER_STEPS).max(2);
+                let ratio = max as f64 / min as f64;
+                (0..steps)
+                    .map(|i| {
+                        let exponent = f64::from(i) / f64::from(steps - 1);
+                        (min as f64 * ratio.powf(exponent)).round() as u64
+                    })
+                    .collect()
+            }
+            _ => {
+                return Err(QuoteError::InvalidRequest(
+                    "Specify either `amounts` or a non-zero `minAmount` <= `maxAmount`".to_string(),
+                ))
+            }
+        };
+        sizes.retain(|size| *size > 0);
+        sizes.sort_unstable();
+        sizes.dedup();
+
+        if sizes.is_empty() {
+            return Err(QuoteError::InvalidRequest(
+                "Ladder needs at least one non-zero amount".to_string(),
+            ));
+        }
+        if sizes.len() > MAX_LADDER_STEPS {
+            return Err(QuoteError::InvalidRequest(format!(
+                "Ladder can have at most {} sizes",
+                MAX_LADDER_STEPS
+            )));
+        }
+        Ok(sizes)
+    }
+}
+
+impl GfxSwapClient {
+    /// Quotes many sizes of a pair against one snapshot of pool state
+    pub async fn quote_ladder(&self, req: &LadderRequest) -> Result<QuoteLadder, QuoteError> {
+        let sizes = req.sizes()?;
+        let swap_mode = req.swap_mode.clone().unwrap_or_default();
+        let clock = self.get_cluster_clock().await?;
+        let snapshot = self
+            .load_pair_snapshot(&req.input_mint, &req.output_mint, clock)
+            .await?;
+
+        let mut steps = Vec::with_capacity(sizes.len());
+        for size in sizes {
+            let computation = match snapshot.compute(size, &swap_mode) {
+                Ok(computation) => computation,
+                Err(QuoteError::AmountOutOfRange(_)) => {
+                    // Larger sizes are out of range as well
+                    log::debug!("Ladder for pool {} stopped at size {}", snapshot.pool, size);
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            steps.push(LadderStep {
+                in_amount: computation.in_amount,
+                out_amount: computation.out_amount,
+                effective_price: effective_price(computation.in_amount, computation.out_amount),
+                price_impact_pct: computation.price_impact.to_string(),
+                fee_breakdown: computation.fee_breakdown,
+            });
+        }
+
+        let max_input_within_impact = match req.max_price_impact_pct {
+            Some(threshold) => max_input_within_impact(&snapshot, threshold)?,
+            None => None,
+        };
+
+        Ok(QuoteLadder {
+            input_mint: req.input_mint,
+            output_mint: req.output_mint,
+            swap_mode,
+            context_slot: snapshot.clock.slot,
+            steps,
+            max_input_within_impact,
+            warnings: snapshot.warnings.clone(),
+        })
+    }
+}
+
+fn effective_price(in_amount: u64, out_amount: u64) -> String {
+    match (Decimal::from_u64(in_amount), Decimal::from_u64(out_amount)) {
+        (Some(in_amount), Some(out_amount)) if !in_amount.is_zero() => {
+            (out_amount / in_amount).to_string()
+        }
+        _ => "0".to_string(),
+    }
+}
+
+/// Binary-searches the largest ExactIn amount whose price impact is within `threshold`. Price
+/// impact grows with size, so the search is over a monotonic predicate.
+fn max_input_within_impact(
+    snapshot: &PairSnapshot,
+    threshold: Decimal,
+) -> Result<Option<ImpactLimit>, QuoteError> {
+    let (input_reserve, _) = snapshot.reserves()?;
+    let best = search_max_amount(max_search_input(input_reserve), |amount| {
+        snapshot
+            .compute(amount, &SwapMode::ExactIn)
+            .ok()
+            .filter(|computation| computation.price_impact <= threshold)
+    });
+
+    Ok(best.map(|computation| ImpactLimit {
+        max_price_impact_pct: threshold.to_string(),
+        in_amount: computation.in_amount,
+        out_amount: computation.out_amount,
+        price_impact_pct: computation.price_impact.to_string(),
+    }))
+}
+
+/// Upper bound of the max-input search. Beyond a multiple of the reserve price impact is near
+/// total, and the reserve plus the input has to fit the u64 vault amount the curve works with.
+fn max_search_input(input_reserve: u64) -> u64 {
+    input_reserve
+        .saturating_mul(MAX_INPUT_SEARCH_RESERVE_MULTIPLE)
+        .min(u64::MAX - input_reserve)
+}
+
+/// The result for the largest amount in `1..=high` that `accept` returns something for, given
+/// that it does for every amount below one it accepts
+fn search_max_amount<T>(high: u64, accept: impl Fn(u64) -> Option<T>) -> Option<T> {
+    let mut low = 0;
+    let mut high = high;
+    let mut best = None;
+    while low < high {
+        let mid = high - (high - low) / 2;
+        match accept(mid) {
+            Some(result) => {
+                low = mid;
+                best = Some(result);
+            }
+            None => high = mid - 1,
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> LadderRequest {
+        LadderRequest {
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            swap_mode: None,
+            amounts: None,
+            min_amount: None,
+            max_amount: None,
+            steps: None,
+            max_price_impact_pct: None,
+        }
+    }
+
+    #[test]
+    fn listed_sizes_are_sorted_and_deduplicated() {
+        let req = LadderRequest {
+            amounts: Some("300, 100,0,200,100".to_string()),
+            ..request()
+        };
+        assert_eq!(req.sizes().unwrap(), vec![100, 200, 300]);
+    }
+
+    #[test]
+    fn rejects_invalid_listed_sizes() {
+        let req = LadderRequest {
+            amounts: Some("100,abc".to_string()),
+            ..request()
+        };
+        assert!(matches!(req.sizes(), Err(QuoteError::InvalidRequest(_))));
+        let req = LadderRequest {
+            amounts: Some("0".to_string()),
+            ..request()
+        };
+        assert!(matches!(req.sizes(), Err(QuoteError::InvalidRequest(_))));
+        let req = LadderRequest {
+            amounts: Some(
+                (1..=101)
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            ..request()
+        };
+        assert!(matches!(req.sizes(), Err(QuoteError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn log_spaced_sizes_span_the_range() {
+        let req = LadderRequest {
+            min_amount: Some(1_000),
+            max_amount: Some(1_000_000),
+            steps: Some(4),
+            ..request()
+        };
+        assert_eq!(
+            req.sizes().unwrap(),
+            vec![1_000, 10_000, 100_000, 1_000_000]
+        );
+    }
+
+    #[test]
+    fn rejects_inverted_ranges() {
+        let req = LadderRequest {
+            min_amount: Some(10),
+            max_amount: Some(1),
+            ..request()
+        };
+        assert!(matches!(req.sizes(), Err(QuoteError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn effective_price_is_output_per_input() {
+        assert_eq!(effective_price(4, 10), "2.5");
+        assert_eq!(effective_price(0, 10), "0");
+    }
+
+    #[test]
+    fn search_finds_the_largest_accepted_amount() {
+        let best = search_max_amount(1_000_000, |amount| (amount <= 123_456).then_some(amount));
+        assert_eq!(best, Some(123_456));
+        assert_eq!(search_max_amount(1_000, |amount| Some(amount)), Some(1_000));
+        assert_eq!(search_max_amount(1_000, |_| None::<u64>), None);
+        assert_eq!(search_max_amount(0, |amount| Some(amount)), None);
+    }
+
+    #[test]
+    fn search_is_bounded_so_reserve_plus_input_fits_a_u64() {
+        assert_eq!(max_search_input(1_000), 100_000);
+        let reserve = u64::MAX / 2;
+        assert_eq!(max_search_input(reserve), u64::MAX - reserve);
+        for reserve in [
+            0,
+            1,
+            1_000,
+            u64::MAX / 100,
+            u64::MAX / 2,
+            u64::MAX - 1,
+            u64::MAX,
+        ] {
+            assert!(reserve.checked_add(max_search_input(reserve)).is_some());
+        }
+    }
+}
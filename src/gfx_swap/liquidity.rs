@@ -0,0 +1,591 @@
+use super::pools::PoolStatus;
+use super::quote::{get_transfer_fee, get_transfer_inverse_fee};
+use super::swap::{calculate_priority_fee, DEFAULT_INSTRUCTION_COMPUTE_UNIT, MIN_CU_PRICE};
+use super::token_policy::{associated_token_account_len, frozen_by_default, TokenPolicyError};
+use super::transfer_hook::get_transfer_hook_program_id;
+use super::GfxSwapClient;
+use crate::accounts::{AccountsError, AccountsGetter};
+use crate::utils::{derive_authority_pda, derive_user_pool_liquidity_pda, encode_transaction};
+
+use anchor_lang::AccountDeserialize;
+use gamma::states::PoolState;
+use serde::{Deserialize, Serialize};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::program_error::ProgramError;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+use spl_token_2022::extension::StateWithExtensionsMut;
+use spl_token_2022::state::Mint;
+use swap_api::serde_helpers::field_as_string;
+use thiserror::Error;
+
+/// Slippage applied to the token bounds when the request doesn't set one
+const DEFAULT_SLIPPAGE_BPS: u16 = 50;
+/// Basis points in 100%
+const BPS_DENOMINATOR: u64 = 10_000;
+
+#[derive(Debug, Error)]
+pub enum LiquidityError {
+    #[error("Error fetching account: {0}")]
+    Accounts(#[from] AccountsError),
+    #[error("Error deserializing anchor account: {0}")]
+    Anchor(#[from] anchor_lang::error::Error),
+    #[error("Error unpacking mint: {0}")]
+    Unpack(#[from] ProgramError),
+    #[error("RPC error: {0}")]
+    ClientError(#[from] solana_rpc_client_api::client_error::Error),
+    #[error(transparent)]
+    SerializeTxn(#[from] bincode::Error),
+    #[error(transparent)]
+    TokenPolicy(#[from] TokenPolicyError),
+    #[error("{0}")]
+    InvalidRequest(String),
+    #[error("Unknown pool {0}")]
+    UnknownPool(Pubkey),
+    #[error("Deposits are disabled for this pool")]
+    DepositDisabled,
+    #[error("Withdrawals are disabled for this pool")]
+    WithdrawDisabled,
+    #[error("{0} has no liquidity in pool {1}")]
+    NoPosition(Pubkey, Pubkey),
+}
+
+/// A deposit or withdrawal, sized by LP tokens or by one of the pool's tokens with the other in
+/// proportion
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LiquidityRequest {
+    #[serde(with = "field_as_string")]
+    pub user_public_key: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub pool: Pubkey,
+    /// LP tokens to mint or burn
+    pub lp_token_amount: Option<u64>,
+    /// Amount of token 0 to deposit or withdraw, instead of `lp_token_amount`
+    pub token_0_amount: Option<u64>,
+    /// Amount of token 1 to deposit or withdraw, instead of `lp_token_amount`
+    pub token_1_amount: Option<u64>,
+    /// Slack on the maximum deposited or minimum withdrawn amounts. Defaults to 50.
+    pub slippage_bps: Option<u16>,
+    /// Wrap SOL deposits and unwrap SOL withdrawals. Defaults to true.
+    pub wrap_and_unwrap_sol: Option<bool>,
+    /// Defaults to the pool's medium priority fee
+    pub compute_unit_price_micro_lamports: Option<u64>,
+}
+
+/// An unsigned deposit or withdrawal transaction and the amounts it moves at current reserves
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LiquidityResponse {
+    /// Base64-encoded transaction for the user to sign
+    pub transaction: String,
+    pub last_valid_block_height: u64,
+    pub lp_token_amount: u64,
+    /// What the user pays for a deposit, or receives from a withdrawal, including transfer fees
+    pub token_0_amount: u64,
+    pub token_1_amount: u64,
+    /// The deposit's maximum or the withdrawal's minimum of token 0, with slippage applied
+    pub token_0_threshold: u64,
+    pub token_1_threshold: u64,
+    pub prioritization_fee_lamports: u64,
+    /// Rent the user puts into token accounts the transaction creates
+    pub ata_rent_lamports: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LiquidityChange {
+    Deposit,
+    Withdraw,
+}
+
+impl GfxSwapClient {
+    pub async fn deposit_transaction(
+        &self,
+        req: LiquidityRequest,
+    ) -> Result<LiquidityResponse, LiquidityError> {
+        self.liquidity_transaction(req, LiquidityChange::Deposit)
+            .await
+    }
+
+    pub async fn withdraw_transaction(
+        &self,
+        req: LiquidityRequest,
+    ) -> Result<LiquidityResponse, LiquidityError> {
+        self.liquidity_transaction(req, LiquidityChange::Withdraw)
+            .await
+    }
+
+    async fn liquidity_transaction(
+        &self,
+        req: LiquidityRequest,
+        change: LiquidityChange,
+    ) -> Result<LiquidityResponse, LiquidityError> {
+        let owner = req.user_public_key;
+        let slippage_bps = u64::from(req.slippage_bps.unwrap_or(DEFAULT_SLIPPAGE_BPS));
+        if slippage_bps > BPS_DENOMINATOR {
+            return Err(LiquidityError::InvalidRequest(format!(
+                "slippageBps must be at most {}",
+                BPS_DENOMINATOR
+            )));
+        }
+
+        let pool_account = match self.accounts_service.get_account(&req.pool).await {
+            Ok(pool_account) => pool_account,
+            Err(AccountsError::NotFound) => return Err(LiquidityError::UnknownPool(req.pool)),
+            Err(e) => return Err(e.into()),
+        };
+        let pool_state = PoolState::try_deserialize(&mut &pool_account[..])?;
+        let clock = self.get_cluster_clock().await?;
+        let pool_status = PoolStatus::new(&pool_state, clock.unix_timestamp);
+        match change {
+            LiquidityChange::Deposit if !pool_status.deposit_enabled => {
+                return Err(LiquidityError::DepositDisabled)
+            }
+            LiquidityChange::Withdraw if !pool_status.withdraw_enabled => {
+                return Err(LiquidityError::WithdrawDisabled)
+            }
+            _ => {}
+        }
+
+        let (reserve_0, reserve_1) = pool_state.vault_amount_without_fee()?;
+        let lp_supply = pool_state.lp_supply;
+        if lp_supply == 0 || reserve_0 == 0 || reserve_1 == 0 {
+            return Err(LiquidityError::InvalidRequest(format!(
+                "Pool {} has no liquidity",
+                req.pool
+            )));
+        }
+        let overflow = || LiquidityError::InvalidRequest("Amount is too large".to_string());
+        let lp_token_amount = match (req.lp_token_amount, req.token_0_amount, req.token_1_amount) {
+            (Some(lp_token_amount), None, None) => lp_token_amount,
+            // Rounded down, so that side moves at most the amount asked for
+            (None, Some(amount), None) => {
+                mul_div(amount, lp_supply, reserve_0, false).ok_or_else(overflow)?
+            }
+            (None, None, Some(amount)) => {
+                mul_div(amount, lp_supply, reserve_1, false).ok_or_else(overflow)?
+            }
+            _ => {
+                return Err(LiquidityError::InvalidRequest(
+                    "Specify exactly one of lpTokenAmount, token0Amount or token1Amount"
+                        .to_string(),
+                ))
+            }
+        };
+        if lp_token_amount == 0 {
+            return Err(LiquidityError::InvalidRequest(
+                "Amount is too small to mint or burn any LP tokens".to_string(),
+            ));
+        }
+        // The program rounds deposits up and withdrawals down, in the pool's favour
+        let round_up = change == LiquidityChange::Deposit;
+        let amount_0 =
+            mul_div(lp_token_amount, reserve_0, lp_supply, round_up).ok_or_else(overflow)?;
+        let amount_1 =
+            mul_div(lp_token_amount, reserve_1, lp_supply, round_up).ok_or_else(overflow)?;
+
+        let mint_0_account = self
+            .accounts_service
+            .get_account(&pool_state.token_0_mint)
+            .await?;
+        let mint_1_account = self
+            .accounts_service
+            .get_account(&pool_state.token_1_mint)
+            .await?;
+        self.token_policy
+            .check_mint(&pool_state.token_0_mint, &mint_0_account)?;
+        self.token_policy
+            .check_mint(&pool_state.token_1_mint, &mint_1_account)?;
+        // Swaps forward hook accounts as remaining accounts, but deposits and withdrawals have no
+        // layout for them, and a hooked transfer without its extra accounts fails
+        for (mint, mint_account) in [
+            (pool_state.token_0_mint, &mint_0_account),
+            (pool_state.token_1_mint, &mint_1_account),
+        ] {
+            if get_transfer_hook_program_id(mint_account).is_some() {
+                return Err(LiquidityError::InvalidRequest(format!(
+                    "Mint {} has a transfer hook, which deposits and withdrawals don't support",
+                    mint
+                )));
+            }
+        }
+        let (token_0_amount, token_0_threshold) =
+            user_amounts(amount_0, &mint_0_account, clock.epoch, change, slippage_bps)?;
+        let (token_1_amount, token_1_threshold) =
+            user_amounts(amount_1, &mint_1_account, clock.epoch, change, slippage_bps)?;
+
+        let token_0_account =
+            spl_associated_token_account::get_associated_token_address_with_program_id(
+                &owner,
+                &pool_state.token_0_mint,
+                &pool_state.token_0_program,
+            );
+        let token_1_account =
+            spl_associated_token_account::get_associated_token_address_with_program_id(
+                &owner,
+                &pool_state.token_1_mint,
+                &pool_state.token_1_program,
+            );
+        let user_pool_liquidity =
+            derive_user_pool_liquidity_pda(&req.pool, &owner, &self.gamma_program_id).0;
+        let mut accounts = self
+            .solana_rpc
+            .get_multiple_accounts_with_commitment(
+                &[user_pool_liquidity, token_0_account, token_1_account],
+                CommitmentConfig::confirmed(),
+            )
+            .await?
+            .value
+            .into_iter();
+        let position_exists = accounts.next().flatten().is_some();
+        let token_0_account_exists = accounts.next().flatten().is_some();
+        let token_1_account_exists = accounts.next().flatten().is_some();
+
+        let mut setup_instructions = Vec::new();
+        let mut cleanup_instructions = Vec::new();
+        // Created ATAs are funded by the user
+        let mut ata_rent_lamports = 0;
+        if !position_exists {
+            match change {
+                LiquidityChange::Deposit => setup_instructions.push(Instruction::new_with_bytes(
+                    self.gamma_program_id,
+                    &anchor_lang::InstructionData::data(
+                        &gamma::instruction::InitUserPoolLiquidity { partner: None },
+                    ),
+                    anchor_lang::ToAccountMetas::to_account_metas(
+                        &gamma::accounts::InitUserPoolLiquidity {
+                            user: owner,
+                            pool_state: req.pool,
+                            user_pool_liquidity,
+                            system_program: solana_sdk::system_program::ID,
+                        },
+                        None,
+                    ),
+                )),
+                LiquidityChange::Withdraw => {
+                    return Err(LiquidityError::NoPosition(owner, req.pool))
+                }
+            }
+        }
+
+        let wrap_and_unwrap_sol = req.wrap_and_unwrap_sol.unwrap_or(true);
+        for (mint, mint_account, token_program, token_account, exists, threshold) in [
+            (
+                pool_state.token_0_mint,
+                &mint_0_account,
+                pool_state.token_0_program,
+                token_0_account,
+                token_0_account_exists,
+                token_0_threshold,
+            ),
+            (
+                pool_state.token_1_mint,
+                &mint_1_account,
+                pool_state.token_1_program,
+                token_1_account,
+                token_1_account_exists,
+                token_1_threshold,
+            ),
+        ] {
+            let wraps_sol = wrap_and_unwrap_sol && mint == spl_token::native_mint::ID;
+            // Withdrawals need accounts to receive both tokens in, deposits only wrapped SOL
+            if !exists {
+                if change == LiquidityChange::Deposit && !wraps_sol {
+                    return Err(LiquidityError::InvalidRequest(format!(
+                        "{} has no {} token account to deposit from",
+                        owner, mint
+                    )));
+                }
+                if frozen_by_default(&mint, mint_account)? {
+                    return Err(TokenPolicyError::FrozenByDefault(mint).into());
+                }
+                setup_instructions.push(
+                    spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                        &owner,
+                        &owner,
+                        &mint,
+                        &token_program,
+                    ),
+                );
+                ata_rent_lamports += Rent::default().minimum_balance(associated_token_account_len(
+                    &mint,
+                    mint_account,
+                    &token_program,
+                )?);
+            }
+            if wraps_sol {
+                if change == LiquidityChange::Deposit {
+                    // The maximum is wrapped, and what the deposit doesn't use is unwrapped after
+                    setup_instructions.extend([
+                        solana_sdk::system_instruction::transfer(&owner, &token_account, threshold),
+                        spl_token::instruction::sync_native(&spl_token::ID, &token_account)
+                            .expect("spl_token::ID is valid"),
+                    ]);
+                }
+                cleanup_instructions.push(
+                    spl_token_2022::instruction::close_account(
+                        &spl_token::ID,
+                        &token_account,
+                        &owner,
+                        &owner,
+                        &[],
+                    )
+                    .expect("spl_token::ID is valid"),
+                );
+            }
+        }
+
+        let authority = derive_authority_pda(&self.gamma_program_id).0;
+        let liquidity_instruction = match change {
+            LiquidityChange::Deposit => Instruction::new_with_bytes(
+                self.gamma_program_id,
+                &anchor_lang::InstructionData::data(&gamma::instruction::Deposit {
+                    lp_token_amount,
+                    maximum_token_0_amount: token_0_threshold,
+                    maximum_token_1_amount: token_1_threshold,
+                }),
+                anchor_lang::ToAccountMetas::to_account_metas(
+                    &gamma::accounts::Deposit {
+                        owner,
+                        authority,
+                        pool_state: req.pool,
+                        user_pool_liquidity,
+                        token_0_account,
+                        token_1_account,
+                        token_0_vault: pool_state.token_0_vault,
+                        token_1_vault: pool_state.token_1_vault,
+                        token_program: spl_token::ID,
+                        token_program_2022: spl_token_2022::ID,
+                        vault_0_mint: pool_state.token_0_mint,
+                        vault_1_mint: pool_state.token_1_mint,
+                    },
+                    None,
+                ),
+            ),
+            LiquidityChange::Withdraw => Instruction::new_with_bytes(
+                self.gamma_program_id,
+                &anchor_lang::InstructionData::data(&gamma::instruction::Withdraw {
+                    lp_token_amount,
+                    minimum_token_0_amount: token_0_threshold,
+                    minimum_token_1_amount: token_1_threshold,
+                }),
+                anchor_lang::ToAccountMetas::to_account_metas(
+                    &gamma::accounts::Withdraw {
+                        owner,
+                        authority,
+                        pool_state: req.pool,
+                        user_pool_liquidity,
+                        token_0_account,
+                        token_1_account,
+                        token_0_vault: pool_state.token_0_vault,
+                        token_1_vault: pool_state.token_1_vault,
+                        token_program: spl_token::ID,
+                        token_program_2022: spl_token_2022::ID,
+                        vault_0_mint: pool_state.token_0_mint,
+                        vault_1_mint: pool_state.token_1_mint,
+                        memo_program: spl_memo::ID,
+                    },
+                    None,
+                ),
+            ),
+        };
+
+        // Deposits and withdrawals write-lock the pool state and both vaults, like swaps
+        let pool_writable_accounts = [req.pool, pool_state.token_0_vault, pool_state.token_1_vault];
+        let cu_price = match (req.compute_unit_price_micro_lamports, &self.priofees_handle) {
+            (Some(cu_price), _) => cu_price,
+            (None, Some(handle)) => std::cmp::max(
+                MIN_CU_PRICE,
+                handle
+                    .get_pool_priofee(&req.pool, &pool_writable_accounts)
+                    .await
+                    .per_compute_unit
+                    .medium,
+            ),
+            (None, None) => MIN_CU_PRICE,
+        };
+        let prioritization_fee_lamports =
+            calculate_priority_fee(cu_price, DEFAULT_INSTRUCTION_COMPUTE_UNIT);
+
+        // The fee is priced for this limit, so the transaction requests it
+        let mut instructions = vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(DEFAULT_INSTRUCTION_COMPUTE_UNIT),
+            ComputeBudgetInstruction::set_compute_unit_price(cu_price),
+        ];
+        instructions.extend(setup_instructions);
+        instructions.push(liquidity_instruction);
+        instructions.extend(cleanup_instructions);
+        let (blockhash, last_valid_block_height) = {
+            let blockhash = self.blockhash.read().await;
+            (blockhash.hash, blockhash.last_valid_block_height)
+        };
+
+        Ok(LiquidityResponse {
+            transaction: encode_transaction(&instructions, &owner, blockhash)?,
+            last_valid_block_height,
+            lp_token_amount,
+            token_0_amount,
+            token_1_amount,
+            token_0_threshold,
+            token_1_threshold,
+            prioritization_fee_lamports,
+            ata_rent_lamports,
+        })
+    }
+}
+
+/// What the user pays or receives once the mint's transfer fee is accounted for, and the bound
+/// the program checks that against, with slippage applied
+fn user_amounts(
+    amount: u64,
+    mint_account: &[u8],
+    epoch: u64,
+    change: LiquidityChange,
+    slippage_bps: u64,
+) -> Result<(u64, u64), LiquidityError> {
+    let mut mint_account = mint_account.to_vec();
+    let mint = StateWithExtensionsMut::<Mint>::unpack(&mut mint_account)?;
+    Ok(match change {
+        // The fee is paid on top, so that the vault receives `amount`
+        LiquidityChange::Deposit => {
+            let amount = amount.saturating_add(get_transfer_inverse_fee(&mint, epoch, amount));
+            let maximum = mul_div(
+                amount,
+                BPS_DENOMINATOR + slippage_bps,
+                BPS_DENOMINATOR,
+                true,
+            )
+            .unwrap_or(u64::MAX);
+            (amount, maximum)
+        }
+        // The fee comes out of what the vault sends
+        LiquidityChange::Withdraw => {
+            let amount = amount.saturating_sub(get_transfer_fee(&mint, epoch, amount));
+            let minimum = mul_div(
+                amount,
+                BPS_DENOMINATOR - slippage_bps,
+                BPS_DENOMINATOR,
+                false,
+            )
+            .unwrap_or(0);
+            (amount, minimum)
+        }
+    })
+}
+
+/// `a * b / c` without intermediate overflow, or `None` if the result doesn't fit a u64
+fn mul_div(a: u64, b: u64, c: u64, round_up: bool) -> Option<u64> {
+    let product = u128::from(a) * u128::from(b);
+    let quotient = if round_up {
+        product.div_ceil(u128::from(c))
+    } else {
+        product / u128::from(c)
+    };
+    u64::try_from(quotient).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spl_token_2022::extension::transfer_fee::{TransferFee, TransferFeeConfig};
+    use spl_token_2022::extension::ExtensionType;
+
+    /// An initialized mint, with a transfer fee of `transfer_fee_bps` if given
+    fn mint_data(transfer_fee_bps: Option<u16>) -> Vec<u8> {
+        let extensions: Vec<ExtensionType> = transfer_fee_bps
+            .iter()
+            .map(|_| ExtensionType::TransferFeeConfig)
+            .collect();
+        let mut data =
+            vec![0; ExtensionType::try_calculate_account_len::<Mint>(&extensions).unwrap()];
+        {
+            let mut mint = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+            if let Some(transfer_fee_bps) = transfer_fee_bps {
+                let config = mint.init_extension::<TransferFeeConfig>(true).unwrap();
+                let transfer_fee = TransferFee {
+                    epoch: 0.into(),
+                    maximum_fee: u64::MAX.into(),
+                    transfer_fee_basis_points: transfer_fee_bps.into(),
+                };
+                config.older_transfer_fee = transfer_fee;
+                config.newer_transfer_fee = transfer_fee;
+            }
+            mint.base = Mint {
+                decimals: 6,
+                is_initialized: true,
+                ..Default::default()
+            };
+            mint.pack_base();
+            mint.init_account_type().unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn mul_div_rounds_as_asked() {
+        assert_eq!(mul_div(10, 1, 3, false), Some(3));
+        assert_eq!(mul_div(10, 1, 3, true), Some(4));
+        assert_eq!(mul_div(9, 1, 3, true), Some(3));
+        assert_eq!(mul_div(u64::MAX, u64::MAX, u64::MAX, false), Some(u64::MAX));
+        assert_eq!(mul_div(u64::MAX, 2, 1, false), None);
+    }
+
+    #[test]
+    fn slippage_bounds_without_transfer_fees() {
+        let mint = mint_data(None);
+        assert_eq!(
+            user_amounts(10_000, &mint, 0, LiquidityChange::Deposit, 50).unwrap(),
+            (10_000, 10_050)
+        );
+        assert_eq!(
+            user_amounts(10_000, &mint, 0, LiquidityChange::Withdraw, 50).unwrap(),
+            (10_000, 9_950)
+        );
+        assert_eq!(
+            user_amounts(10_000, &mint, 0, LiquidityChange::Withdraw, 10_000).unwrap(),
+            (10_000, 0)
+        );
+    }
+
+    #[test]
+    fn slippage_bounds_round_against_the_user() {
+        let mint = mint_data(None);
+        assert_eq!(
+            user_amounts(999, &mint, 0, LiquidityChange::Deposit, 1).unwrap(),
+            (999, 1_000)
+        );
+        assert_eq!(
+            user_amounts(999, &mint, 0, LiquidityChange::Withdraw, 1).unwrap(),
+            (999, 998)
+        );
+    }
+
+    #[test]
+    fn deposits_pay_the_transfer_fee_on_top() {
+        // 10_102 less its 1% fee of 102 leaves the 10_000 the vault needs
+        let mint = mint_data(Some(100));
+        assert_eq!(
+            user_amounts(10_000, &mint, 0, LiquidityChange::Deposit, 50).unwrap(),
+            (10_102, 10_153)
+        );
+    }
+
+    #[test]
+    fn withdrawals_receive_less_the_transfer_fee() {
+        let mint = mint_data(Some(100));
+        assert_eq!(
+            user_amounts(10_000, &mint, 0, LiquidityChange::Withdraw, 50).unwrap(),
+            (9_900, 9_850)
+        );
+    }
+
+    #[test]
+    fn rejects_data_that_isnt_a_mint() {
+        assert!(matches!(
+            user_amounts(10_000, &[0; 10], 0, LiquidityChange::Deposit, 50),
+            Err(LiquidityError::Unpack(_))
+        ));
+    }
+}
@@ -1,15 +1,43 @@
+pub mod execute;
+pub mod ladder;
+pub mod liquidity;
+pub mod pools;
+pub mod price;
 pub mod quote;
+pub mod quote_cache;
+pub mod referral;
+pub mod slippage;
+pub mod sponsor;
 pub mod swap;
+pub mod token_policy;
+pub mod transfer_hook;
+pub mod twap;
+pub mod verify;
 
 use crate::accounts::service::AccountsService;
 use crate::blockhash_polling::RecentBlockhash;
+use crate::gfx_swap::price::PriceIndex;
+use crate::gfx_swap::quote_cache::QuoteCache;
+use crate::gfx_swap::slippage::PoolActivity;
+use crate::gfx_swap::sponsor::Sponsor;
+use crate::gfx_swap::token_policy::TokenExtensionPolicy;
+use crate::gfx_swap::verify::QuoteVerification;
+use crate::jito::JitoClient;
 use crate::priofee::PrioFeesHandle;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_rpc_client_api::client_error::{Error as ClientError, ErrorKind as ClientErrorKind};
+use solana_sdk::clock::Clock;
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::sysvar;
 use tokio::sync::RwLock;
 
+/// How long a fetched cluster clock is reused for streamed quotes, about one slot
+const RECENT_CLOCK_TTL: Duration = Duration::from_millis(400);
+
 #[derive(Clone)]
 pub struct GfxSwapClient {
     /// Solana RPC client
@@ -35,4 +63,69 @@ pub struct GfxSwapClient {
 
     /// The referral program
     pub referral_program: Option<Pubkey>,
+
+    /// Project new referral accounts are created under
+    pub referral_project: Option<Pubkey>,
+
+    /// Referral accounts requests can name besides `referral`
+    pub allowed_referrals: HashSet<Pubkey>,
+
+    /// Which token-2022 mints can be quoted and swapped
+    pub token_policy: TokenExtensionPolicy,
+
+    /// How posted quotes are checked before swaps are built from them
+    pub quote_verification: QuoteVerification,
+
+    /// Recently issued quotes, for swaps that reference a quote by ID
+    pub quote_cache: QuoteCache,
+
+    /// Recent pool updates, for recommending slippage
+    pub pool_activity: PoolActivity,
+
+    /// Pays for sponsored swaps, if enabled
+    pub sponsor: Option<Sponsor>,
+
+    /// Jito block engine for tips and bundles
+    pub jito: JitoClient,
+
+    /// Whether swaps check balances and token accounts unless the request says otherwise
+    pub preflight_checks: bool,
+
+    /// The last cluster clock fetched for streamed quotes, and when
+    pub recent_clock: Arc<RwLock<Option<(Instant, Clock)>>>,
+
+    /// The last price index built from pool reserves, and when
+    pub recent_price_index: Arc<RwLock<Option<(Instant, Arc<PriceIndex>)>>>,
+}
+
+impl GfxSwapClient {
+    /// The cluster's clock. Pool open-times are checked against this on-chain, not wall-clock time
+    pub async fn get_cluster_clock(&self) -> Result<Clock, ClientError> {
+        let account = self.solana_rpc.get_account(&sysvar::clock::ID).await?;
+        solana_sdk::account::from_account::<Clock, _>(&account).ok_or_else(|| {
+            ClientErrorKind::Custom("Failed to deserialize clock sysvar".to_string()).into()
+        })
+    }
+
+    /// The cluster's clock, reused for up to a slot. Streamed quotes are pushed on every account
+    /// update, so subscribers share one fetch per slot rather than fetching per push.
+    pub async fn get_recent_cluster_clock(&self) -> Result<Clock, ClientError> {
+        let fresh = |recent: &Option<(Instant, Clock)>| {
+            recent
+                .as_ref()
+                .filter(|(fetched_at, _)| fetched_at.elapsed() < RECENT_CLOCK_TTL)
+                .map(|(_, clock)| clock.clone())
+        };
+        if let Some(clock) = fresh(&*self.recent_clock.read().await) {
+            return Ok(clock);
+        }
+        let mut recent = self.recent_clock.write().await;
+        // Another subscriber may have refreshed it while this one waited for the lock
+        if let Some(clock) = fresh(&recent) {
+            return Ok(clock);
+        }
+        let clock = self.get_cluster_clock().await?;
+        *recent = Some((Instant::now(), clock.clone()));
+        Ok(clock)
+    }
 }
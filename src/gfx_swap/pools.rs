@@ -0,0 +1,89 @@
+use super::GfxSwapClient;
+use crate::accounts::{AccountsError, AccountsGetter};
+
+use anchor_lang::AccountDeserialize;
+use gamma::states::{PoolState, PoolStatusBitIndex};
+use log::error;
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use swap_api::serde_helpers::field_as_string;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PoolsError {
+    #[error("Error fetching account: {0}")]
+    Accounts(#[from] AccountsError),
+    #[error("RPC error: {0}")]
+    ClientError(#[from] solana_rpc_client_api::client_error::Error),
+}
+
+/// What a pool currently allows, as enforced by the program
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolStatus {
+    pub swap_enabled: bool,
+    pub deposit_enabled: bool,
+    pub withdraw_enabled: bool,
+    /// Unix timestamp after which the pool accepts swaps
+    pub open_time: u64,
+    /// Whether the cluster time is past `open_time`
+    pub is_open: bool,
+}
+
+impl PoolStatus {
+    pub fn new(pool_state: &PoolState, cluster_time: i64) -> Self {
+        let open_time = pool_state.open_time;
+        PoolStatus {
+            swap_enabled: pool_state.get_status_by_bit(PoolStatusBitIndex::Swap),
+            deposit_enabled: pool_state.get_status_by_bit(PoolStatusBitIndex::Deposit),
+            withdraw_enabled: pool_state.get_status_by_bit(PoolStatusBitIndex::Withdraw),
+            open_time,
+            // The program requires the block time to be strictly after the open time
+            is_open: u64::try_from(cluster_time).is_ok_and(|now| now > open_time),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolInfo {
+    #[serde(with = "field_as_string")]
+    pub address: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub token_0_mint: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub token_1_mint: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub token_0_vault_amount: u64,
+    #[serde(with = "field_as_string")]
+    pub token_1_vault_amount: u64,
+    pub status: PoolStatus,
+}
+
+impl GfxSwapClient {
+    /// All pools tracked by the accounts service
+    pub async fn pools(&self) -> Result<Vec<PoolInfo>, PoolsError> {
+        let clock = self.get_cluster_clock().await?;
+        let mut pools = vec![];
+        for pool in self.accounts_service.pools() {
+            let pool_account = self.accounts_service.get_account(&pool).await?;
+            let pool_state = match PoolState::try_deserialize(&mut &pool_account[..]) {
+                Ok(pool_state) => pool_state,
+                Err(e) => {
+                    error!("Failed to deserialize pool {}: {}", pool, e);
+                    continue;
+                }
+            };
+            pools.push(PoolInfo {
+                address: pool,
+                token_0_mint: pool_state.token_0_mint,
+                token_1_mint: pool_state.token_1_mint,
+                token_0_vault_amount: pool_state.token_0_vault_amount,
+                token_1_vault_amount: pool_state.token_1_vault_amount,
+                status: PoolStatus::new(&pool_state, clock.unix_timestamp),
+            });
+        }
+
+        Ok(pools)
+    }
+}
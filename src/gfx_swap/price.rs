@@ -0,0 +1,527 @@
+use super::GfxSwapClient;
+use crate::accounts::AccountsGetter;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anchor_lang::AccountDeserialize;
+use gamma::states::PoolState;
+use log::error;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use swap_api::serde_helpers::field_as_string;
+use thiserror::Error;
+
+pub const USDC_MINT: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+/// Tokens that prices are chained through when a token has no pool with the vs-token
+const REFERENCE_MINTS: [Pubkey; 2] = [spl_token::native_mint::ID, USDC_MINT];
+/// Upper bound on the number of tokens priced in one request
+const MAX_PRICE_IDS: usize = 100;
+/// How long a price index is reused for, about one slot. Building one reads every tracked pool.
+const RECENT_PRICE_INDEX_TTL: Duration = Duration::from_millis(400);
+/// Routes within this fraction of the weighted price, with a direct one among them, are high
+/// confidence
+const HIGH_CONFIDENCE_DEVIATION: Decimal = Decimal::from_parts(1, 0, 0, false, 2);
+/// Routes beyond this fraction of the weighted price are low confidence
+const LOW_CONFIDENCE_DEVIATION: Decimal = Decimal::from_parts(5, 0, 0, false, 2);
+
+#[derive(Debug, Error)]
+pub enum PriceError {
+    #[error("{0}")]
+    InvalidRequest(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceRequest {
+    /// Comma-separated mints to price
+    pub ids: String,
+    /// The mint, or `USDC`/`SOL`, that prices are quoted in. Defaults to USDC
+    pub vs_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceResponse {
+    /// Prices by mint. Tokens that can't be priced map to `null`
+    pub data: HashMap<String, Option<TokenPrice>>,
+    pub time_taken: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenPrice {
+    #[serde(with = "field_as_string")]
+    pub id: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub vs_token: Pubkey,
+    /// Price of one whole token in whole vs-tokens
+    pub price: String,
+    /// Liquidity behind the price in vs-token units, taken as the thinnest leg of each route
+    pub liquidity: String,
+    pub confidence: PriceConfidence,
+    /// Pools the price was derived from
+    #[serde(with = "pubkeys_as_strings")]
+    pub pools: Vec<Pubkey>,
+}
+
+/// How much a price can be trusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PriceConfidence {
+    /// There's a direct pool with the vs-token, and any other routes agree within 1%
+    High,
+    /// Routes agree within 5%
+    Medium,
+    /// Routes disagree by more than 5%
+    Low,
+}
+
+/// A pool's tradeable reserves in whole-token units
+struct PoolReserves {
+    pool: Pubkey,
+    mint_0: Pubkey,
+    mint_1: Pubkey,
+    reserve_0: Decimal,
+    reserve_1: Decimal,
+}
+
+impl PoolReserves {
+    /// Price of `base` in the pool's other token, and the reserve of that other token
+    fn spot(&self, base: &Pubkey) -> Option<(Decimal, Decimal)> {
+        let (base_reserve, quote_reserve) = if *base == self.mint_0 {
+            (self.reserve_0, self.reserve_1)
+        } else {
+            (self.reserve_1, self.reserve_0)
+        };
+        Some((quote_reserve.checked_div(base_reserve)?, quote_reserve))
+    }
+}
+
+/// One way of pricing a token, either through a direct pool or through a reference token
+struct PriceRoute {
+    price: Decimal,
+    liquidity: Decimal,
+    pools: Vec<Pubkey>,
+    direct: bool,
+}
+
+/// Spot prices derived from the reserves of all tracked pools
+pub struct PriceIndex {
+    pools: HashMap<(Pubkey, Pubkey), PoolReserves>,
+    decimals: HashMap<Pubkey, u8>,
+}
+
+impl PriceIndex {
+    /// Adds a pool from its raw reserves. Pools whose mints have more decimals than a `Decimal`
+    /// can scale to are left out, and `false` is returned.
+    fn insert_pool(
+        &mut self,
+        pool: Pubkey,
+        (mint_0, decimals_0, reserve_0): (Pubkey, u8, u64),
+        (mint_1, decimals_1, reserve_1): (Pubkey, u8, u64),
+    ) -> bool {
+        let (Some(reserve_0), Some(reserve_1)) = (
+            whole_tokens(reserve_0, decimals_0),
+            whole_tokens(reserve_1, decimals_1),
+        ) else {
+            return false;
+        };
+        self.decimals.insert(mint_0, decimals_0);
+        self.decimals.insert(mint_1, decimals_1);
+        self.pools.insert(
+            (std::cmp::min(mint_0, mint_1), std::cmp::max(mint_0, mint_1)),
+            PoolReserves {
+                pool,
+                mint_0,
+                mint_1,
+                reserve_0,
+                reserve_1,
+            },
+        );
+        true
+    }
+
+    fn pool(&self, mint_a: &Pubkey, mint_b: &Pubkey) -> Option<&PoolReserves> {
+        let key = (
+            std::cmp::min(*mint_a, *mint_b),
+            std::cmp::max(*mint_a, *mint_b),
+        );
+        self.pools.get(&key)
+    }
+
+    fn direct_route(&self, mint: &Pubkey, vs_token: &Pubkey) -> Option<PriceRoute> {
+        let pool = self.pool(mint, vs_token)?;
+        let (price, vs_reserve) = pool.spot(mint)?;
+        Some(PriceRoute {
+            price,
+            liquidity: vs_reserve.checked_mul(Decimal::TWO)?,
+            pools: vec![pool.pool],
+            direct: true,
+        })
+    }
+
+    fn chained_route(
+        &self,
+        mint: &Pubkey,
+        reference: &Pubkey,
+        vs_token: &Pubkey,
+    ) -> Option<PriceRoute> {
+        let first = self.direct_route(mint, reference)?;
+        let second = self.direct_route(reference, vs_token)?;
+        Some(PriceRoute {
+            price: first.price.checked_mul(second.price)?,
+            liquidity: std::cmp::min(first.liquidity.checked_mul(second.price)?, second.liquidity),
+            pools: [first.pools, second.pools].concat(),
+            direct: false,
+        })
+    }
+
+    /// Prices `mint` in `vs_token`, averaging the available routes weighted by their liquidity.
+    /// Returns the price, the total liquidity and the routes used.
+    fn weighted_price(
+        &self,
+        mint: &Pubkey,
+        vs_token: &Pubkey,
+    ) -> Option<(Decimal, Decimal, Vec<PriceRoute>)> {
+        let routes = self
+            .direct_route(mint, vs_token)
+            .into_iter()
+            .chain(
+                REFERENCE_MINTS
+                    .iter()
+                    .filter(|reference| *reference != mint && *reference != vs_token)
+                    .filter_map(|reference| self.chained_route(mint, reference, vs_token)),
+            )
+            .filter(|route| route.liquidity > Decimal::ZERO)
+            .collect::<Vec<_>>();
+
+        let (price, liquidity) = liquidity_weighted(&routes)?;
+        Some((price, liquidity, routes))
+    }
+
+    pub fn price(&self, mint: &Pubkey, vs_token: &Pubkey) -> Option<TokenPrice> {
+        if mint == vs_token {
+            return Some(TokenPrice {
+                id: *mint,
+                vs_token: *vs_token,
+                price: Decimal::ONE.to_string(),
+                liquidity: Decimal::ZERO.to_string(),
+                confidence: PriceConfidence::High,
+                pools: vec![],
+            });
+        }
+
+        let (price, liquidity, routes) = self.weighted_price(mint, vs_token)?;
+        let confidence = confidence(&routes, price);
+
+        Some(TokenPrice {
+            id: *mint,
+            vs_token: *vs_token,
+            price: price.normalize().to_string(),
+            liquidity: liquidity.round_dp(6).to_string(),
+            confidence,
+            pools: routes.into_iter().flat_map(|route| route.pools).collect(),
+        })
+    }
+
+    /// The USD value of a raw `amount` of `mint`
+    pub fn usd_value(&self, mint: &Pubkey, amount: u64) -> Option<Decimal> {
+        let decimals = self.decimals.get(mint)?;
+        let price = if *mint == USDC_MINT {
+            Decimal::ONE
+        } else {
+            self.weighted_price(mint, &USDC_MINT)?.0
+        };
+        whole_tokens(amount, *decimals)?.checked_mul(price)
+    }
+}
+
+/// The average of the routes' prices weighted by their liquidity, and their total liquidity
+fn liquidity_weighted(routes: &[PriceRoute]) -> Option<(Decimal, Decimal)> {
+    let liquidity = routes.iter().try_fold(Decimal::ZERO, |total, route| {
+        total.checked_add(route.liquidity)
+    })?;
+    let price = routes
+        .iter()
+        .try_fold(Decimal::ZERO, |total, route| {
+            total.checked_add(route.price.checked_mul(route.liquidity)?)
+        })?
+        .checked_div(liquidity)?;
+    Some((price, liquidity))
+}
+
+/// How far the routes stray from their weighted `price`, and whether one of them is direct
+fn confidence(routes: &[PriceRoute], price: Decimal) -> PriceConfidence {
+    let max_deviation = routes
+        .iter()
+        .filter_map(|route| route.price.checked_sub(price)?.checked_div(price))
+        .map(|deviation| deviation.abs())
+        .max()
+        .unwrap_or_default();
+    if max_deviation > LOW_CONFIDENCE_DEVIATION {
+        PriceConfidence::Low
+    } else if routes.iter().any(|route| route.direct) && max_deviation <= HIGH_CONFIDENCE_DEVIATION
+    {
+        PriceConfidence::High
+    } else {
+        PriceConfidence::Medium
+    }
+}
+
+/// A raw token amount in whole tokens, or `None` if `decimals` is beyond `Decimal`'s scale
+fn whole_tokens(amount: u64, decimals: u8) -> Option<Decimal> {
+    Decimal::try_from_i128_with_scale(i128::from(amount), u32::from(decimals)).ok()
+}
+
+impl PriceRequest {
+    fn parse(&self) -> Result<(Vec<Pubkey>, Pubkey), PriceError> {
+        let vs_token = match self.vs_token.as_deref() {
+            None | Some("USDC") => USDC_MINT,
+            Some("SOL") => spl_token::native_mint::ID,
+            Some(mint) => Pubkey::from_str(mint)
+                .map_err(|_| PriceError::InvalidRequest(format!("Invalid vsToken: {}", mint)))?,
+        };
+        let ids = self
+            .ids
+            .split(',')
+            .map(|id| {
+                Pubkey::from_str(id.trim())
+                    .map_err(|_| PriceError::InvalidRequest(format!("Invalid id: {}", id)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if ids.len() > MAX_PRICE_IDS {
+            return Err(PriceError::InvalidRequest(format!(
+                "At most {} ids can be priced at once",
+                MAX_PRICE_IDS
+            )));
+        }
+
+        Ok((ids, vs_token))
+    }
+}
+
+impl GfxSwapClient {
+    /// A price index of the tracked pools' reserves, rebuilt at most once a slot
+    pub async fn price_index(&self) -> Arc<PriceIndex> {
+        let fresh = |recent: &Option<(Instant, Arc<PriceIndex>)>| {
+            recent
+                .as_ref()
+                .filter(|(built_at, _)| built_at.elapsed() < RECENT_PRICE_INDEX_TTL)
+                .map(|(_, index)| Arc::clone(index))
+        };
+        if let Some(index) = fresh(&*self.recent_price_index.read().await) {
+            return index;
+        }
+        let mut recent = self.recent_price_index.write().await;
+        // Another request may have rebuilt it while this one waited for the lock
+        if let Some(index) = fresh(&recent) {
+            return index;
+        }
+        let index = Arc::new(self.build_price_index().await);
+        *recent = Some((Instant::now(), Arc::clone(&index)));
+        index
+    }
+
+    /// Builds a price index from the current reserves of all tracked pools
+    async fn build_price_index(&self) -> PriceIndex {
+        let mut index = PriceIndex {
+            pools: HashMap::new(),
+            decimals: HashMap::new(),
+        };
+        for pool in self.accounts_service.pools() {
+            let pool_state = match self.accounts_service.get_account(&pool).await {
+                Ok(data) => match PoolState::try_deserialize(&mut &data[..]) {
+                    Ok(pool_state) => pool_state,
+                    Err(e) => {
+                        error!("Failed to deserialize pool {}: {}", pool, e);
+                        continue;
+                    }
+                },
+                Err(e) => {
+                    error!("Failed to get pool {} for pricing: {}", pool, e);
+                    continue;
+                }
+            };
+            let Ok((reserve_0, reserve_1)) = pool_state.vault_amount_without_fee() else {
+                error!("Failed to get reserves of pool {} for pricing", pool);
+                continue;
+            };
+
+            if !index.insert_pool(
+                pool,
+                (
+                    pool_state.token_0_mint,
+                    pool_state.mint_0_decimals,
+                    reserve_0,
+                ),
+                (
+                    pool_state.token_1_mint,
+                    pool_state.mint_1_decimals,
+                    reserve_1,
+                ),
+            ) {
+                log::debug!("Pool {} has mints with too many decimals to price", pool);
+            }
+        }
+
+        index
+    }
+
+    pub async fn price(&self, req: &PriceRequest) -> Result<PriceResponse, PriceError> {
+        let start = std::time::Instant::now();
+        let (ids, vs_token) = req.parse()?;
+        let index = self.price_index().await;
+        let data = ids
+            .into_iter()
+            .map(|id| (id.to_string(), index.price(&id, &vs_token)))
+            .collect();
+
+        Ok(PriceResponse {
+            data,
+            time_taken: start.elapsed().as_secs_f64(),
+        })
+    }
+}
+
+mod pubkeys_as_strings {
+    use serde::{Serialize, Serializer};
+    use solana_sdk::pubkey::Pubkey;
+
+    pub fn serialize<S: Serializer>(pubkeys: &[Pubkey], serializer: S) -> Result<S::Ok, S::Error> {
+        pubkeys
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> PriceIndex {
+        PriceIndex {
+            pools: HashMap::new(),
+            decimals: HashMap::new(),
+        }
+    }
+
+    fn route(price: Decimal, liquidity: Decimal, direct: bool) -> PriceRoute {
+        PriceRoute {
+            price,
+            liquidity,
+            pools: vec![],
+            direct,
+        }
+    }
+
+    #[test]
+    fn routes_are_weighted_by_liquidity() {
+        let routes = [
+            route(Decimal::ONE, Decimal::new(100, 0), true),
+            route(Decimal::new(11, 1), Decimal::new(300, 0), false),
+        ];
+        assert_eq!(
+            liquidity_weighted(&routes),
+            Some((Decimal::new(1075, 3), Decimal::new(400, 0)))
+        );
+        assert_eq!(liquidity_weighted(&[]), None);
+    }
+
+    #[test]
+    fn confidence_thresholds() {
+        let price = Decimal::ONE;
+        let at = |deviation_pct: i64, direct: bool| {
+            route(
+                Decimal::ONE + Decimal::new(deviation_pct, 2),
+                Decimal::ONE,
+                direct,
+            )
+        };
+        // Within 1% with a direct pool
+        assert_eq!(
+            confidence(&[at(0, true), at(1, false)], price),
+            PriceConfidence::High
+        );
+        // Within 1%, but only through reference tokens
+        assert_eq!(confidence(&[at(1, false)], price), PriceConfidence::Medium);
+        assert_eq!(
+            confidence(&[at(0, true), at(-5, false)], price),
+            PriceConfidence::Medium
+        );
+        assert_eq!(
+            confidence(&[at(0, true), at(6, false)], price),
+            PriceConfidence::Low
+        );
+    }
+
+    #[test]
+    fn chains_through_sol_to_usdc() {
+        let mut index = index();
+        let mint = Pubkey::new_unique();
+        let sol = spl_token::native_mint::ID;
+        // 1 token = 0.5 SOL, 1 SOL = 100 USDC
+        let mint_sol = Pubkey::new_unique();
+        let sol_usdc = Pubkey::new_unique();
+        assert!(index.insert_pool(mint_sol, (mint, 6, 2_000_000), (sol, 9, 1_000_000_000)));
+        assert!(index.insert_pool(
+            sol_usdc,
+            (sol, 9, 10_000_000_000),
+            (USDC_MINT, 6, 1_000_000_000)
+        ));
+
+        let price = index.price(&mint, &USDC_MINT).unwrap();
+        assert_eq!(price.price, "50");
+        // The thinner leg, 2 SOL of depth worth 200 USDC, bounds the route
+        assert_eq!(
+            Decimal::from_str(&price.liquidity).unwrap(),
+            Decimal::new(200, 0)
+        );
+        assert_eq!(price.confidence, PriceConfidence::Medium);
+        assert_eq!(price.pools, vec![mint_sol, sol_usdc]);
+
+        let price = index.price(&sol, &USDC_MINT).unwrap();
+        assert_eq!(price.price, "100");
+        assert_eq!(price.confidence, PriceConfidence::High);
+    }
+
+    #[test]
+    fn skips_pools_with_more_decimals_than_decimal_can_scale() {
+        let mut index = index();
+        let mint = Pubkey::new_unique();
+        assert!(!index.insert_pool(
+            Pubkey::new_unique(),
+            (mint, 30, 1_000),
+            (USDC_MINT, 6, 1_000_000),
+        ));
+        assert!(index.price(&mint, &USDC_MINT).is_none());
+        assert!(index.usd_value(&mint, 1_000).is_none());
+    }
+
+    #[test]
+    fn usd_value_in_whole_tokens() {
+        let mut index = index();
+        let mint = Pubkey::new_unique();
+        // 10 tokens against 25 USDC
+        assert!(index.insert_pool(
+            Pubkey::new_unique(),
+            (mint, 9, 10_000_000_000),
+            (USDC_MINT, 6, 25_000_000),
+        ));
+        assert_eq!(
+            index.usd_value(&mint, 2_000_000_000),
+            Some(Decimal::new(5, 0))
+        );
+        assert_eq!(
+            index.usd_value(&USDC_MINT, 1_500_000),
+            Some(Decimal::new(15, 1))
+        );
+        assert_eq!(whole_tokens(1, 28), Some(Decimal::new(1, 28)));
+        assert_eq!(whole_tokens(1, 29), None);
+    }
+}
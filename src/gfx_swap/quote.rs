@@ -1,16 +1,26 @@
-use crate::accounts::{AccountsError, AccountsGetter};
+use crate::accounts::{AccountData, AccountsError, AccountsGetter};
+use crate::gfx_swap::pools::PoolStatus;
+use crate::gfx_swap::price::PriceIndex;
+use crate::gfx_swap::quote_cache::QuoteSignature;
+use crate::gfx_swap::slippage::{DynamicSlippageReport, DEFAULT_MAX_AUTO_SLIPPAGE_BPS};
+use crate::gfx_swap::token_policy::TokenPolicyError;
+use crate::gfx_swap::transfer_hook::{get_transfer_hook_program_id, TransferHookMint};
 use crate::gfx_swap::GfxSwapClient;
 use crate::utils::derive_pool_pda;
 
-use std::ops::{Div, Mul, Sub};
+use std::collections::HashMap;
+use std::ops::Mul;
 use std::time::{Instant, SystemTime};
 
 use anchor_lang::AccountDeserialize;
-use gamma::curve::{CurveCalculator, TradeDirection};
+use gamma::curve::CurveCalculator;
 use gamma::states::{AmmConfig, ObservationState, PoolState};
 use rust_decimal::prelude::FromPrimitive;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use solana_sdk::clock::Clock;
 use solana_sdk::program_error::ProgramError;
+use solana_sdk::pubkey::Pubkey;
 use spl_token_2022::{
     extension::transfer_fee::{TransferFeeConfig, MAX_FEE_BASIS_POINTS},
     extension::{BaseState, BaseStateWithExtensions, StateWithExtensionsMut},
@@ -18,8 +28,14 @@ use spl_token_2022::{
 };
 use swap_api::quote::{QuoteRequest, QuoteResponse, SwapMode};
 use swap_api::route_plan_with_metadata::{RoutePlanStep, SwapInfo};
+use swap_api::serde_helpers::field_as_string;
 use thiserror::Error;
 
+/// Denominator for the fee rates stored in `AmmConfig`
+const FEE_RATE_DENOMINATOR: u128 = 1_000_000;
+/// Upper bound on the number of quotes in one batch
+pub const MAX_BATCH_QUOTES: usize = 100;
+
 #[derive(Debug, Error)]
 pub enum QuoteError {
     #[error("Error fetching account: {0}")]
@@ -34,89 +50,162 @@ pub enum QuoteError {
     InvalidRequest(String),
     #[error("No pool exists for this input-mint - output-mint pair")]
     PairNotTradeable,
+    #[error(transparent)]
+    TokenPolicy(#[from] TokenPolicyError),
+    #[error("Swaps are disabled for this pool")]
+    PoolDisabled,
+    #[error("Pool is not open for swaps until unix timestamp {opens_at}")]
+    PoolNotOpen { opens_at: u64 },
+    #[error("Amount {0} is too large to quote against this pool")]
+    AmountOutOfRange(u64),
     #[error("{0}")]
     Any(#[from] anyhow::Error),
 }
 
-impl GfxSwapClient {
-    pub async fn quote(&self, quote: &QuoteRequest) -> Result<QuoteResponse, QuoteError> {
-        let start = Instant::now();
-        let epoch_info = self.solana_rpc.get_epoch_info().await?;
-        let min_context_slot = epoch_info.absolute_slot;
-        let epoch = epoch_info.epoch;
+/// Opt-in extensions to the jupiter-compatible quote request. These are parsed from the same
+/// query string as the `QuoteRequest`, so clients that don't know about them are unaffected.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuoteOptions {
+    /// Include an itemized `feeBreakdown` in the response
+    #[serde(default)]
+    pub include_fee_breakdown: bool,
+    /// Include the swap's `swapUsdValue`, priced from pool reserves
+    #[serde(default)]
+    pub include_swap_usd_value: bool,
+    /// Pick the slippage from recent pool volatility, activity and trade size, instead of the
+    /// request's `slippageBps`
+    #[serde(default)]
+    pub auto_slippage: bool,
+    /// Upper bound on the auto slippage
+    pub max_auto_slippage_bps: Option<u16>,
+}
 
-        if quote.input_mint == quote.output_mint {
-            return Err(QuoteError::InvalidRequest(
-                "Input mint cannot equal output mint".to_string(),
-            ));
-        }
+/// A jupiter-compatible `QuoteResponse` with Gamma-specific extensions. Optional extension
+/// fields are omitted from the wire format unless requested.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GammaQuoteResponse {
+    #[serde(flatten)]
+    pub quote: QuoteResponse,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee_breakdown: Option<FeeBreakdown>,
+    /// Mints in this quote with a transfer-hook. Swaps for these carry extra accounts resolved
+    /// from the hook program.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub transfer_hook_mints: Vec<TransferHookMint>,
+    /// Things about the traded mints users should know before swapping, e.g a permanent delegate
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_usd_value: Option<String>,
+    /// How the slippage was chosen, for `autoSlippage` quotes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dynamic_slippage_report: Option<DynamicSlippageReport>,
+    /// Swaps can reference this quote by ID until `expiry_slot`
+    pub quote_id: String,
+    pub expiry_slot: u64,
+    /// HMAC over the quote's swap parameters, ID and expiry
+    pub signature: String,
+}
 
-        let token_0_mint = std::cmp::min(quote.input_mint, quote.output_mint);
-        let token_1_mint = std::cmp::max(quote.input_mint, quote.output_mint);
-        let (pool, _) = derive_pool_pda(
-            &self.gamma_config,
-            &token_0_mint,
-            &token_1_mint,
-            &self.gamma_program_id,
-        );
+impl GammaQuoteResponse {
+    /// Values the swap in USD by its input, or by its output if the input can't be priced
+    pub fn set_swap_usd_value(&mut self, price_index: &PriceIndex) {
+        self.swap_usd_value = price_index
+            .usd_value(&self.quote.input_mint, self.quote.in_amount)
+            .or_else(|| price_index.usd_value(&self.quote.output_mint, self.quote.out_amount))
+            .map(|value| value.round_dp(6).to_string());
+    }
+}
 
-        let pool_account = self
-            .accounts_service
-            .get_account(&pool)
-            .await
-            .map_err(|_| QuoteError::PairNotTradeable)?;
-        let pool_state = PoolState::try_deserialize(&mut &pool_account[..])?;
-        let observation = pool_state.observation_key;
+/// Itemized fees charged for a quote.
+/// `lp_fee + protocol_fee + fund_fee` make up the total trade fee taken by the pool, and
+/// `dynamic_fee` is the part of that total charged on top of the config's base trade-fee rate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeBreakdown {
+    /// Share of the trade fee retained by liquidity providers
+    pub lp_fee: FeeItem,
+    /// Share of the trade fee collected by the protocol
+    pub protocol_fee: FeeItem,
+    /// Share of the trade fee collected by the fund
+    pub fund_fee: FeeItem,
+    /// Volatility-based component of the trade fee
+    pub dynamic_fee: FeeItem,
+    /// Token-2022 transfer fee charged on the input token
+    pub input_transfer_fee: FeeItem,
+    /// Token-2022 transfer fee charged on the output token
+    pub output_transfer_fee: FeeItem,
+}
 
-        let amm_config_account = self
-            .accounts_service
-            .get_account(&self.gamma_config)
-            .await?;
-        let mut token_0_mint_account = self.accounts_service.get_account(&token_0_mint).await?;
-        let mut token_1_mint_account = self.accounts_service.get_account(&token_1_mint).await?;
-        let observation_account = self.accounts_service.get_account(&observation).await?;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeItem {
+    #[serde(with = "field_as_string")]
+    pub amount: u64,
+    #[serde(with = "field_as_string")]
+    pub mint: Pubkey,
+}
 
-        let amm_config = AmmConfig::try_deserialize(&mut &amm_config_account[..])?;
-        let observation_state = ObservationState::try_deserialize(&mut &observation_account[..])?;
-        let token_0_mint_info = StateWithExtensionsMut::<Mint>::unpack(&mut token_0_mint_account)?;
-        let token_1_mint_info = StateWithExtensionsMut::<Mint>::unpack(&mut token_1_mint_account)?;
+/// The accounts needed to quote a pair, read from the accounts store once. Quotes computed from
+/// the same snapshot are mutually consistent.
+pub struct PairSnapshot {
+    pub pool: Pubkey,
+    pub pool_state: PoolState,
+    pub amm_config: AmmConfig,
+    pub observation_state: ObservationState,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    input_mint_account: AccountData,
+    output_mint_account: AccountData,
+    /// The cluster clock the snapshot was taken at
+    pub clock: Clock,
+    pub transfer_hook_mints: Vec<TransferHookMint>,
+    pub warnings: Vec<String>,
+}
 
-        let swap_mode = quote.swap_mode.clone().unwrap_or_default();
+/// A single point on the pair's execution curve
+pub struct QuoteComputation {
+    pub in_amount: u64,
+    pub out_amount: u64,
+    /// The amount computed by the curve, i.e `out_amount` for ExactIn and `in_amount` for ExactOut
+    pub other_amount: u64,
+    /// Total trade fee taken by the pool, in the input token
+    pub trade_fee: u64,
+    pub price_impact: Decimal,
+    pub fee_breakdown: FeeBreakdown,
+}
+
+impl PairSnapshot {
+    /// The pool's tradeable (input, output) reserves, excluding accrued protocol and fund fees
+    pub fn reserves(&self) -> Result<(u64, u64), QuoteError> {
+        let (total_token_0_amount, total_token_1_amount) =
+            self.pool_state.vault_amount_without_fee()?;
+        Ok(if self.input_mint == self.pool_state.token_0_mint {
+            (total_token_0_amount, total_token_1_amount)
+        } else {
+            (total_token_1_amount, total_token_0_amount)
+        })
+    }
+
+    /// Runs the curve for `amount`, which is the input for ExactIn and the output for ExactOut
+    pub fn compute(
+        &self,
+        amount: u64,
+        swap_mode: &SwapMode,
+    ) -> Result<QuoteComputation, QuoteError> {
         let base_in = match swap_mode {
             SwapMode::ExactIn => true,
             SwapMode::ExactOut => false,
         };
+        let epoch = self.clock.epoch;
+        let mut input_mint_account = self.input_mint_account.clone();
+        let mut output_mint_account = self.output_mint_account.clone();
+        let input_token_mint = StateWithExtensionsMut::<Mint>::unpack(&mut input_mint_account)?;
+        let output_token_mint = StateWithExtensionsMut::<Mint>::unpack(&mut output_mint_account)?;
 
-        let token_0_vault_amount = pool_state.token_0_vault_amount;
-        let token_1_vault_amount = pool_state.token_1_vault_amount;
-        log::debug!("Pool: {}", pool);
-        log::debug!("Token0 vault amount: {}", token_0_vault_amount);
-        log::debug!("Token1 vault amount: {}", token_1_vault_amount);
-        let (total_token_0_amount, total_token_1_amount) = pool_state.vault_amount_without_fee()?;
-
-        let (
-            _trade_direction,
-            total_input_token_amount,
-            total_output_token_amount,
-            input_token_mint,
-            output_token_mint,
-        ) = if quote.input_mint == pool_state.token_0_mint {
-            (
-                TradeDirection::ZeroForOne,
-                total_token_0_amount,
-                total_token_1_amount,
-                token_0_mint_info,
-                token_1_mint_info,
-            )
-        } else {
-            (
-                TradeDirection::OneForZero,
-                total_token_1_amount,
-                total_token_0_amount,
-                token_1_mint_info,
-                token_0_mint_info,
-            )
-        };
+        let (total_input_token_amount, total_output_token_amount) = self.reserves()?;
 
         let current_unix_timestamp = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
@@ -124,7 +213,7 @@ impl GfxSwapClient {
             .as_secs();
 
         let actual_amount_specified = get_amount_after_transfer_fee(
-            quote.amount,
+            amount,
             if base_in {
                 &input_token_mint
             } else {
@@ -134,15 +223,23 @@ impl GfxSwapClient {
             epoch,
         );
 
+        // Sizes far beyond the pool's reserves produce amounts the u64 and decimal math can't hold
+        let out_of_range = || QuoteError::AmountOutOfRange(amount);
+        // Nor can the pool pay out its whole output reserve
+        if !base_in && u128::from(actual_amount_specified) >= u128::from(total_output_token_amount)
+        {
+            return Err(out_of_range());
+        }
+
         let swap_result = if base_in {
             CurveCalculator::swap_base_input(
                 u128::from(actual_amount_specified),
                 u128::from(total_input_token_amount),
                 u128::from(total_output_token_amount),
-                &amm_config,
-                &pool_state,
+                &self.amm_config,
+                &self.pool_state,
                 current_unix_timestamp,
-                &observation_state,
+                &self.observation_state,
                 false
             )
         } else {
@@ -150,10 +247,10 @@ impl GfxSwapClient {
                 u128::from(actual_amount_specified),
                 u128::from(total_input_token_amount),
                 u128::from(total_output_token_amount),
-                &amm_config,
-                &pool_state,
+                &self.amm_config,
+                &self.pool_state,
                 current_unix_timestamp,
-                &observation_state,
+                &self.observation_state,
                 false
             )
         }?;
@@ -163,7 +260,7 @@ impl GfxSwapClient {
         } else {
             swap_result.source_amount_swapped
         })
-        .unwrap();
+        .map_err(|_| out_of_range())?;
 
         let other_amount = get_amount_after_transfer_fee(
             other_amount,
@@ -176,24 +273,312 @@ impl GfxSwapClient {
             epoch,
         );
 
-        let other_amount_threshold =
-            amount_with_slippage(other_amount, quote.slippage_bps as f64 / 10_000.0, !base_in);
-
         let (in_amount, out_amount) = if base_in {
-            (quote.amount, other_amount)
+            (amount, other_amount)
         } else {
-            (other_amount, quote.amount)
+            (other_amount, amount)
         };
 
-        let fee_amount = u64::try_from(swap_result.dynamic_fee).unwrap();
-        let initial_price = Decimal::from_u64(total_input_token_amount - fee_amount)
-            .unwrap()
-            .div(Decimal::from_u64(total_output_token_amount).unwrap());
+        let fee_amount = u64::try_from(swap_result.dynamic_fee).map_err(|_| out_of_range())?;
+        let initial_price = total_input_token_amount
+            .checked_sub(fee_amount)
+            .and_then(|amount| {
+                Decimal::from(amount).checked_div(Decimal::from(total_output_token_amount))
+            })
+            .ok_or_else(out_of_range)?;
         let final_price = Decimal::from_u128(swap_result.new_swap_source_amount)
-            .unwrap()
-            .div(Decimal::from_u128(swap_result.new_swap_destination_amount).unwrap());
-        let price_impact =
-            (Decimal::from(1).sub(initial_price.div(final_price))).mul(Decimal::from(100));
+            .zip(Decimal::from_u128(swap_result.new_swap_destination_amount))
+            .and_then(|(source, destination)| source.checked_div(destination))
+            .ok_or_else(out_of_range)?;
+        let price_impact = initial_price
+            .checked_div(final_price)
+            .and_then(|ratio| Decimal::from(1).checked_sub(ratio))
+            .and_then(|impact| impact.checked_mul(Decimal::from(100)))
+            .ok_or_else(out_of_range)?;
+
+        let trade_fee = swap_result.dynamic_fee;
+        let protocol_fee =
+            trade_fee * u128::from(self.amm_config.protocol_fee_rate) / FEE_RATE_DENOMINATOR;
+        let fund_fee = trade_fee * u128::from(self.amm_config.fund_fee_rate) / FEE_RATE_DENOMINATOR;
+        let lp_fee = trade_fee.saturating_sub(protocol_fee + fund_fee);
+        // Same rounding as the program's base trade-fee: ceil(amount * rate / denominator)
+        let base_trade_fee = (swap_result.source_amount_swapped
+            * u128::from(self.amm_config.trade_fee_rate))
+        .div_ceil(FEE_RATE_DENOMINATOR);
+        let dynamic_fee = trade_fee.saturating_sub(base_trade_fee);
+
+        let source_amount_swapped =
+            u64::try_from(swap_result.source_amount_swapped).map_err(|_| out_of_range())?;
+        let destination_amount_swapped =
+            u64::try_from(swap_result.destination_amount_swapped).map_err(|_| out_of_range())?;
+        // Fees are parts of the swapped source amount, so they fit once it does
+        let input_fee_item = |amount: u128| FeeItem {
+            amount: u64::try_from(amount).unwrap_or(u64::MAX),
+            mint: self.input_mint,
+        };
+        let fee_breakdown = FeeBreakdown {
+            lp_fee: input_fee_item(lp_fee),
+            protocol_fee: input_fee_item(protocol_fee),
+            fund_fee: input_fee_item(fund_fee),
+            dynamic_fee: input_fee_item(dynamic_fee),
+            input_transfer_fee: FeeItem {
+                amount: in_amount.saturating_sub(source_amount_swapped),
+                mint: self.input_mint,
+            },
+            output_transfer_fee: FeeItem {
+                amount: destination_amount_swapped.saturating_sub(out_amount),
+                mint: self.output_mint,
+            },
+        };
+
+        Ok(QuoteComputation {
+            in_amount,
+            out_amount,
+            other_amount,
+            trade_fee: fee_amount,
+            price_impact,
+            fee_breakdown,
+        })
+    }
+}
+
+impl GfxSwapClient {
+    pub async fn quote(&self, quote: &QuoteRequest) -> Result<QuoteResponse, QuoteError> {
+        Ok(self
+            .quote_with_options(quote, &QuoteOptions::default())
+            .await?
+            .quote)
+    }
+
+    pub async fn quote_with_options(
+        &self,
+        quote: &QuoteRequest,
+        options: &QuoteOptions,
+    ) -> Result<GammaQuoteResponse, QuoteError> {
+        let clock = self.get_cluster_clock().await?;
+        let response = self.quote_with_options_at(quote, options, clock).await?;
+        // Only single quotes are kept for swap-by-ID, so batches and streams can't evict them
+        self.quote_cache
+            .store(&response.quote_id, &response.quote, response.expiry_slot);
+        Ok(response)
+    }
+
+    /// Quotes against a cluster clock the caller already has
+    pub async fn quote_with_options_at(
+        &self,
+        quote: &QuoteRequest,
+        options: &QuoteOptions,
+        clock: Clock,
+    ) -> Result<GammaQuoteResponse, QuoteError> {
+        let start = Instant::now();
+        let snapshot = self
+            .load_pair_snapshot(&quote.input_mint, &quote.output_mint, clock)
+            .await?;
+        let mut response = self.quote_from_snapshot(&snapshot, quote, options, start)?;
+        if options.include_swap_usd_value {
+            response.set_swap_usd_value(&self.price_index().await);
+        }
+        Ok(response)
+    }
+
+    /// Quotes every request against the same cluster clock, loading each pair's accounts once.
+    /// Fails as a whole only if the batch itself is invalid or the clock can't be fetched.
+    pub async fn quote_batch(
+        &self,
+        quotes: &[QuoteRequest],
+        options: &QuoteOptions,
+    ) -> Result<Vec<Result<GammaQuoteResponse, QuoteError>>, QuoteError> {
+        if quotes.len() > MAX_BATCH_QUOTES {
+            return Err(QuoteError::InvalidRequest(format!(
+                "Batch can have at most {} quotes",
+                MAX_BATCH_QUOTES
+            )));
+        }
+
+        let start = Instant::now();
+        let clock = self.get_cluster_clock().await?;
+        let mut snapshots = HashMap::<(Pubkey, Pubkey), PairSnapshot>::new();
+        let mut results = Vec::with_capacity(quotes.len());
+        for quote in quotes {
+            let pair = (quote.input_mint, quote.output_mint);
+            if !snapshots.contains_key(&pair) {
+                match self
+                    .load_pair_snapshot(&quote.input_mint, &quote.output_mint, clock.clone())
+                    .await
+                {
+                    Ok(snapshot) => _ = snapshots.insert(pair, snapshot),
+                    Err(e) => {
+                        results.push(Err(e));
+                        continue;
+                    }
+                }
+            }
+            results.push(self.quote_from_snapshot(&snapshots[&pair], quote, options, start));
+        }
+
+        if options.include_swap_usd_value {
+            let price_index = self.price_index().await;
+            for response in results.iter_mut().flatten() {
+                response.set_swap_usd_value(&price_index);
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// The address of the pool for a pair, whether or not it exists
+    pub fn pool_for_pair(&self, input_mint: &Pubkey, output_mint: &Pubkey) -> Pubkey {
+        let token_0_mint = std::cmp::min(*input_mint, *output_mint);
+        let token_1_mint = std::cmp::max(*input_mint, *output_mint);
+        let (pool, _) = derive_pool_pda(
+            &self.gamma_config,
+            &token_0_mint,
+            &token_1_mint,
+            &self.gamma_program_id,
+        );
+        pool
+    }
+
+    /// Accounts whose changes can move a pair's quote: the pool, the amm config and, once the
+    /// pool is known, its observation account
+    pub async fn quote_dependencies(
+        &self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+    ) -> Vec<Pubkey> {
+        let pool = self.pool_for_pair(input_mint, output_mint);
+        let mut keys = vec![pool, self.gamma_config];
+        if let Some(pool_state) = self
+            .accounts_service
+            .get_account(&pool)
+            .await
+            .ok()
+            .and_then(|data| PoolState::try_deserialize(&mut &data[..]).ok())
+        {
+            keys.push(pool_state.observation_key);
+        }
+        keys
+    }
+
+    /// Reads the pool and related accounts for a pair from the accounts store, and checks that
+    /// the pair can be traded at the given cluster time
+    pub async fn load_pair_snapshot(
+        &self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        clock: Clock,
+    ) -> Result<PairSnapshot, QuoteError> {
+        if input_mint == output_mint {
+            return Err(QuoteError::InvalidRequest(
+                "Input mint cannot equal output mint".to_string(),
+            ));
+        }
+
+        let pool = self.pool_for_pair(input_mint, output_mint);
+        let pool_account = self
+            .accounts_service
+            .get_account(&pool)
+            .await
+            .map_err(|_| QuoteError::PairNotTradeable)?;
+        let pool_state = PoolState::try_deserialize(&mut &pool_account[..])?;
+        let pool_status = PoolStatus::new(&pool_state, clock.unix_timestamp);
+        if !pool_status.swap_enabled {
+            return Err(QuoteError::PoolDisabled);
+        }
+        if !pool_status.is_open {
+            return Err(QuoteError::PoolNotOpen {
+                opens_at: pool_status.open_time,
+            });
+        }
+        let observation = pool_state.observation_key;
+
+        let amm_config_account = self
+            .accounts_service
+            .get_account(&self.gamma_config)
+            .await?;
+        let input_mint_account = self.accounts_service.get_account(input_mint).await?;
+        let output_mint_account = self.accounts_service.get_account(output_mint).await?;
+        let observation_account = self.accounts_service.get_account(&observation).await?;
+
+        let mut warnings = self
+            .token_policy
+            .check_mint(input_mint, &input_mint_account)?;
+        warnings.extend(
+            self.token_policy
+                .check_mint(output_mint, &output_mint_account)?,
+        );
+        let transfer_hook_mints = [
+            (*input_mint, &input_mint_account),
+            (*output_mint, &output_mint_account),
+        ]
+        .into_iter()
+        .filter_map(|(mint, mint_account)| {
+            get_transfer_hook_program_id(mint_account)
+                .map(|program_id| TransferHookMint { mint, program_id })
+        })
+        .collect();
+
+        let amm_config = AmmConfig::try_deserialize(&mut &amm_config_account[..])?;
+        let observation_state = ObservationState::try_deserialize(&mut &observation_account[..])?;
+
+        log::debug!("Pool: {}", pool);
+        log::debug!("Token0 vault amount: {}", pool_state.token_0_vault_amount);
+        log::debug!("Token1 vault amount: {}", pool_state.token_1_vault_amount);
+
+        Ok(PairSnapshot {
+            pool,
+            pool_state,
+            amm_config,
+            observation_state,
+            input_mint: *input_mint,
+            output_mint: *output_mint,
+            input_mint_account,
+            output_mint_account,
+            clock,
+            transfer_hook_mints,
+            warnings,
+        })
+    }
+
+    pub fn quote_from_snapshot(
+        &self,
+        snapshot: &PairSnapshot,
+        quote: &QuoteRequest,
+        options: &QuoteOptions,
+        start: Instant,
+    ) -> Result<GammaQuoteResponse, QuoteError> {
+        let swap_mode = quote.swap_mode.clone().unwrap_or_default();
+        let base_in = match swap_mode {
+            SwapMode::ExactIn => true,
+            SwapMode::ExactOut => false,
+        };
+        let computation = snapshot.compute(quote.amount, &swap_mode)?;
+        let QuoteComputation {
+            in_amount,
+            out_amount,
+            other_amount,
+            trade_fee,
+            price_impact,
+            fee_breakdown,
+        } = computation;
+
+        let dynamic_slippage_report = options
+            .auto_slippage
+            .then(|| {
+                snapshot.dynamic_slippage(
+                    in_amount,
+                    self.pool_activity.updates_per_minute(&snapshot.pool),
+                    options
+                        .max_auto_slippage_bps
+                        .unwrap_or(DEFAULT_MAX_AUTO_SLIPPAGE_BPS),
+                )
+            })
+            .transpose()?;
+        let slippage_bps = dynamic_slippage_report
+            .as_ref()
+            .map_or(quote.slippage_bps, |report| report.slippage_bps);
+        let other_amount_threshold =
+            amount_with_slippage(other_amount, slippage_bps as f64 / 10_000.0, !base_in);
 
         let response = QuoteResponse {
             input_mint: quote.input_mint,
@@ -202,7 +587,7 @@ impl GfxSwapClient {
             out_amount,
             other_amount_threshold,
             swap_mode,
-            slippage_bps: quote.slippage_bps,
+            slippage_bps,
             platform_fee: None, // todo!(),
             price_impact_pct: price_impact.to_string(),
             route_plan: vec![RoutePlanStep {
@@ -213,16 +598,32 @@ impl GfxSwapClient {
                     output_mint: quote.output_mint,
                     in_amount,
                     out_amount,
-                    fee_amount,
+                    fee_amount: trade_fee,
                     fee_mint: quote.input_mint,
                 },
                 percent: 100,
             }],
-            context_slot: min_context_slot,
+            context_slot: snapshot.clock.slot,
             time_taken: start.elapsed().as_secs_f64(),
         };
 
-        Ok(response)
+        let QuoteSignature {
+            quote_id,
+            expiry_slot,
+            signature,
+        } = self.quote_cache.sign(&response, snapshot.clock.slot);
+
+        Ok(GammaQuoteResponse {
+            quote: response,
+            fee_breakdown: options.include_fee_breakdown.then_some(fee_breakdown),
+            transfer_hook_mints: snapshot.transfer_hook_mints.clone(),
+            warnings: snapshot.warnings.clone(),
+            swap_usd_value: None,
+            dynamic_slippage_report,
+            quote_id,
+            expiry_slot,
+            signature,
+        })
     }
 }
 
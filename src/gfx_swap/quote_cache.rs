@@ -0,0 +1,215 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use swap_api::quote::{QuoteResponse, SwapMode};
+
+/// How many slots a quote can be swapped by reference for, by default. About a minute.
+const DEFAULT_QUOTE_TTL_SLOTS: u64 = 150;
+/// How many quotes are kept for swap-by-reference, by default
+const DEFAULT_QUOTE_CACHE_SIZE: usize = 10_000;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A quote issued by this server
+#[derive(Clone)]
+pub struct CachedQuote {
+    pub quote: QuoteResponse,
+    pub expiry_slot: u64,
+    pub issued_at: Instant,
+}
+
+/// The ID, expiry and signature a quote is issued with
+pub struct QuoteSignature {
+    pub quote_id: String,
+    pub expiry_slot: u64,
+    pub signature: String,
+}
+
+/// Keeps recent quotes so swaps can reference them by ID, and signs quotes so they can be
+/// checked when clients post them back in full
+#[derive(Clone)]
+pub struct QuoteCache {
+    key: Arc<Vec<u8>>,
+    ttl_slots: u64,
+    capacity: usize,
+    quotes: Arc<Mutex<QuoteEntries>>,
+}
+
+#[derive(Default)]
+struct QuoteEntries {
+    by_id: HashMap<String, CachedQuote>,
+    /// Quote IDs from oldest to newest, for eviction
+    order: VecDeque<String>,
+}
+
+impl QuoteCache {
+    /// Without a signing key, a random one is generated, so signatures don't survive restarts
+    pub fn new(key: Option<String>, ttl_slots: Option<u64>, capacity: Option<usize>) -> Self {
+        let key = key.map(String::into_bytes).unwrap_or_else(|| {
+            let mut key = vec![0; 32];
+            rand::thread_rng().fill_bytes(&mut key);
+            key
+        });
+        QuoteCache {
+            key: Arc::new(key),
+            ttl_slots: ttl_slots.unwrap_or(DEFAULT_QUOTE_TTL_SLOTS),
+            capacity: capacity.unwrap_or(DEFAULT_QUOTE_CACHE_SIZE),
+            quotes: Arc::default(),
+        }
+    }
+
+    /// Returns the ID, expiry and signature a quote computed at `slot` is issued with. Signed
+    /// quotes can be posted back in full; only stored ones can be referenced by ID.
+    pub fn sign(&self, quote: &QuoteResponse, slot: u64) -> QuoteSignature {
+        let mut id = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut id);
+        let quote_id = hex::encode(id);
+        let expiry_slot = slot.saturating_add(self.ttl_slots);
+        let signature = hex::encode(
+            self.mac(&quote_id, expiry_slot, quote)
+                .finalize()
+                .into_bytes(),
+        );
+        QuoteSignature {
+            quote_id,
+            expiry_slot,
+            signature,
+        }
+    }
+
+    /// Keeps a signed quote so swaps can reference it by ID, evicting the oldest when full
+    pub fn store(&self, quote_id: &str, quote: &QuoteResponse, expiry_slot: u64) {
+        let mut quotes = self.quotes.lock().expect("quote cache lock poisoned");
+        if quotes.order.len() >= self.capacity {
+            if let Some(evicted) = quotes.order.pop_front() {
+                quotes.by_id.remove(&evicted);
+            }
+        }
+        quotes.order.push_back(quote_id.to_string());
+        quotes.by_id.insert(
+            quote_id.to_string(),
+            CachedQuote {
+                quote: quote.clone(),
+                expiry_slot,
+                issued_at: Instant::now(),
+            },
+        );
+    }
+
+    pub fn get(&self, quote_id: &str) -> Option<CachedQuote> {
+        self.quotes
+            .lock()
+            .expect("quote cache lock poisoned")
+            .by_id
+            .get(quote_id)
+            .cloned()
+    }
+
+    /// Whether `signature` was issued by this server for the quote
+    pub fn verify(
+        &self,
+        quote_id: &str,
+        expiry_slot: u64,
+        quote: &QuoteResponse,
+        signature: &str,
+    ) -> bool {
+        hex::decode(signature).is_ok_and(|signature| {
+            self.mac(quote_id, expiry_slot, quote)
+                .verify_slice(&signature)
+                .is_ok()
+        })
+    }
+
+    /// Signs the fields a swap is built from, so clients can't alter them and keep the signature
+    fn mac(&self, quote_id: &str, expiry_slot: u64, quote: &QuoteResponse) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC takes keys of any size");
+        mac.update(quote_id.as_bytes());
+        mac.update(&expiry_slot.to_le_bytes());
+        mac.update(quote.input_mint.as_ref());
+        mac.update(quote.output_mint.as_ref());
+        mac.update(&quote.in_amount.to_le_bytes());
+        mac.update(&quote.out_amount.to_le_bytes());
+        mac.update(&quote.other_amount_threshold.to_le_bytes());
+        mac.update(&[match quote.swap_mode {
+            SwapMode::ExactIn => 0,
+            SwapMode::ExactOut => 1,
+        }]);
+        mac.update(&quote.slippage_bps.to_le_bytes());
+        mac.update(&quote.context_slot.to_le_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn quote() -> QuoteResponse {
+        QuoteResponse {
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            in_amount: 1_000,
+            out_amount: 2_000,
+            other_amount_threshold: 1_990,
+            swap_mode: SwapMode::ExactIn,
+            slippage_bps: 50,
+            platform_fee: None,
+            price_impact_pct: "0".to_string(),
+            route_plan: vec![],
+            context_slot: 100,
+            time_taken: 0.0,
+        }
+    }
+
+    #[test]
+    fn signatures_cover_the_swapped_fields() {
+        let cache = QuoteCache::new(Some("key".to_string()), Some(10), None);
+        let quote = quote();
+        let signed = cache.sign(&quote, 100);
+        assert_eq!(signed.expiry_slot, 110);
+        assert!(cache.verify(&signed.quote_id, 110, &quote, &signed.signature));
+        assert!(!cache.verify(&signed.quote_id, 111, &quote, &signed.signature));
+        let altered = QuoteResponse {
+            other_amount_threshold: 0,
+            ..quote.clone()
+        };
+        assert!(!cache.verify(&signed.quote_id, 110, &altered, &signed.signature));
+        assert!(!cache.verify(&signed.quote_id, 110, &quote, "not hex"));
+
+        let other_key = QuoteCache::new(Some("other".to_string()), Some(10), None);
+        assert!(!other_key.verify(&signed.quote_id, 110, &quote, &signed.signature));
+    }
+
+    #[test]
+    fn only_stored_quotes_can_be_referenced() {
+        let cache = QuoteCache::new(None, None, None);
+        let quote = quote();
+        let signed = cache.sign(&quote, 100);
+        assert!(cache.get(&signed.quote_id).is_none());
+        cache.store(&signed.quote_id, &quote, signed.expiry_slot);
+        let cached = cache.get(&signed.quote_id).unwrap();
+        assert_eq!(cached.expiry_slot, signed.expiry_slot);
+        assert_eq!(cached.quote.out_amount, quote.out_amount);
+    }
+
+    #[test]
+    fn evicts_the_oldest_quote_when_full() {
+        let cache = QuoteCache::new(None, None, Some(2));
+        let quote = quote();
+        let ids: Vec<String> = (0..3)
+            .map(|_| {
+                let signed = cache.sign(&quote, 100);
+                cache.store(&signed.quote_id, &quote, signed.expiry_slot);
+                signed.quote_id
+            })
+            .collect();
+        assert!(cache.get(&ids[0]).is_none());
+        assert!(cache.get(&ids[1]).is_some());
+        assert!(cache.get(&ids[2]).is_some());
+    }
+}
@@ -0,0 +1,435 @@
+use super::GfxSwapClient;
+use crate::accounts::{AccountsError, AccountsGetter};
+use crate::utils::encode_transaction;
+
+use anchor_lang::AccountDeserialize;
+use gamma::states::PoolState;
+use log::error;
+use serde::{Deserialize, Serialize};
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::{pubkey, pubkey::Pubkey};
+use spl_token_2022::extension::StateWithExtensions;
+use std::collections::BTreeMap;
+use swap_api::serde_helpers::{field_as_string, option_field_as_string};
+use thiserror::Error;
+
+/// ID of the referral program
+pub const REFERRAL_PROGRAM_MAINNET: Pubkey = pubkey!("REFER4ZgmyYx9c6He5XfaTMiGfdLwRnkV4RPp9t9iF3");
+/// Seed of the token accounts a referral account collects fees in
+const REFERRAL_ATA_SEED: &[u8] = b"referral_ata";
+/// Seed of named referral accounts
+const REFERRAL_SEED: &[u8] = b"referral";
+/// Program-enforced limit of a PDA seed, and so of a referral account name
+const MAX_NAME_LEN: usize = 32;
+/// Token accounts created per transaction, which keeps transactions well within size limits
+const TOKEN_ACCOUNTS_PER_TRANSACTION: usize = 10;
+/// Most accounts `getMultipleAccounts` returns at once
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+#[derive(Debug, Error)]
+pub enum ReferralError {
+    #[error("Error fetching account: {0}")]
+    Accounts(#[from] AccountsError),
+    #[error("RPC error: {0}")]
+    ClientError(#[from] solana_rpc_client_api::client_error::Error),
+    #[error(transparent)]
+    SerializeTxn(#[from] bincode::Error),
+    #[error("{0}")]
+    InvalidRequest(String),
+    #[error("{0} is not a referral account of program {1}")]
+    NotAReferralAccount(Pubkey, Pubkey),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeReferralAccountRequest {
+    /// Pays for the referral account and signs
+    #[serde(with = "field_as_string")]
+    pub payer: Pubkey,
+    /// Receives the referral's share of fees. Defaults to the payer.
+    #[serde(default, with = "option_field_as_string")]
+    pub partner: Option<Pubkey>,
+    /// Project to create the account under. Defaults to the server's `--referral-project`.
+    #[serde(default, with = "option_field_as_string")]
+    pub project: Option<Pubkey>,
+    /// Names the account, whose address is derived from it
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeReferralAccountResponse {
+    #[serde(with = "field_as_string")]
+    pub referral_account: Pubkey,
+    /// Base64 of the unsigned transaction
+    pub transaction: String,
+    pub last_valid_block_height: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeReferralTokenAccountsRequest {
+    /// Pays for the token accounts and signs
+    #[serde(with = "field_as_string")]
+    pub payer: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub referral_account: Pubkey,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeReferralTokenAccountsResponse {
+    /// Base64 of unsigned transactions, each creating a batch of the missing token accounts
+    pub transactions: Vec<String>,
+    /// Mints whose token accounts the transactions create, in order
+    pub mints: Vec<String>,
+    pub last_valid_block_height: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReferralFeesReport {
+    #[serde(with = "field_as_string")]
+    pub referral_account: Pubkey,
+    /// Fees held in each existing referral token account of a traded mint
+    pub fees: Vec<ReferralFees>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReferralFees {
+    #[serde(with = "field_as_string")]
+    pub mint: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub token_account: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub amount: u64,
+}
+
+impl GfxSwapClient {
+    pub fn referral_program(&self) -> Pubkey {
+        self.referral_program.unwrap_or(REFERRAL_PROGRAM_MAINNET)
+    }
+
+    /// The account `referral_account` collects fees in `mint` in
+    pub fn referral_token_account(&self, referral_account: &Pubkey, mint: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[REFERRAL_ATA_SEED, referral_account.as_ref(), mint.as_ref()],
+            &self.referral_program(),
+        )
+        .0
+    }
+
+    /// Whether swaps can name `referral_account` as their referrer. The server's own referral
+    /// account always can.
+    pub fn is_referral_allowed(&self, referral_account: &Pubkey) -> bool {
+        self.referral == Some(*referral_account)
+            || self.allowed_referrals.contains(referral_account)
+    }
+
+    /// An unsigned transaction creating a named referral account
+    pub async fn initialize_referral_account(
+        &self,
+        req: InitializeReferralAccountRequest,
+    ) -> Result<InitializeReferralAccountResponse, ReferralError> {
+        if req.name.is_empty() || req.name.len() > MAX_NAME_LEN {
+            return Err(ReferralError::InvalidRequest(format!(
+                "name must be 1 to {} bytes",
+                MAX_NAME_LEN
+            )));
+        }
+        let project = req.project.or(self.referral_project).ok_or_else(|| {
+            ReferralError::InvalidRequest(
+                "project is required, the server has no referral project configured".to_string(),
+            )
+        })?;
+        let referral_program = self.referral_program();
+        let referral_account = Pubkey::find_program_address(
+            &[REFERRAL_SEED, project.as_ref(), req.name.as_bytes()],
+            &referral_program,
+        )
+        .0;
+
+        let instruction = initialize_referral_account_instruction(
+            &referral_program,
+            &req.payer,
+            &req.partner.unwrap_or(req.payer),
+            &project,
+            &referral_account,
+            &req.name,
+        );
+        let (blockhash, last_valid_block_height) = self.latest_blockhash().await;
+        Ok(InitializeReferralAccountResponse {
+            referral_account,
+            transaction: encode_transaction(&[instruction], &req.payer, blockhash)?,
+            last_valid_block_height,
+        })
+    }
+
+    /// Unsigned transactions creating the referral token accounts missing for mints traded in
+    /// tracked pools
+    pub async fn initialize_referral_token_accounts(
+        &self,
+        req: InitializeReferralTokenAccountsRequest,
+    ) -> Result<InitializeReferralTokenAccountsResponse, ReferralError> {
+        let project = self.referral_project_of(&req.referral_account).await?;
+        let referral_program = self.referral_program();
+        let mints = self.traded_mints().await?;
+        let token_accounts: Vec<Pubkey> = mints
+            .keys()
+            .map(|mint| self.referral_token_account(&req.referral_account, mint))
+            .collect();
+        let existing = self.get_multiple_accounts(&token_accounts).await?;
+
+        let missing: Vec<(Pubkey, Pubkey, Pubkey)> = mints
+            .into_iter()
+            .zip(token_accounts)
+            .zip(existing)
+            .filter(|(_, account)| account.is_none())
+            .map(|(((mint, token_program), token_account), _)| (mint, token_program, token_account))
+            .collect();
+        let discriminator = anchor_discriminator("initialize_referral_token_account");
+        let (blockhash, last_valid_block_height) = self.latest_blockhash().await;
+        let transactions = missing
+            .chunks(TOKEN_ACCOUNTS_PER_TRANSACTION)
+            .map(|chunk| {
+                let instructions: Vec<Instruction> = chunk
+                    .iter()
+                    .map(|(mint, token_program, token_account)| {
+                        Instruction::new_with_bytes(
+                            referral_program,
+                            &discriminator,
+                            vec![
+                                AccountMeta::new(req.payer, true),
+                                AccountMeta::new_readonly(project, false),
+                                AccountMeta::new_readonly(req.referral_account, false),
+                                AccountMeta::new(*token_account, false),
+                                AccountMeta::new_readonly(*mint, false),
+                                AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+                                AccountMeta::new_readonly(*token_program, false),
+                            ],
+                        )
+                    })
+                    .collect();
+                encode_transaction(&instructions, &req.payer, blockhash)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(InitializeReferralTokenAccountsResponse {
+            transactions,
+            mints: missing
+                .iter()
+                .map(|(mint, _, _)| mint.to_string())
+                .collect(),
+            last_valid_block_height,
+        })
+    }
+
+    /// Fees the referral account holds in each mint traded in tracked pools
+    pub async fn referral_fees(
+        &self,
+        referral_account: &Pubkey,
+    ) -> Result<ReferralFeesReport, ReferralError> {
+        self.referral_project_of(referral_account).await?;
+        let mints: Vec<Pubkey> = self.traded_mints().await?.into_keys().collect();
+        let token_accounts: Vec<Pubkey> = mints
+            .iter()
+            .map(|mint| self.referral_token_account(referral_account, mint))
+            .collect();
+        let accounts = self.get_multiple_accounts(&token_accounts).await?;
+
+        let mut fees = vec![];
+        for ((mint, token_account), account) in mints.into_iter().zip(token_accounts).zip(accounts)
+        {
+            let Some(account) = account else {
+                continue;
+            };
+            match StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data) {
+                Ok(state) => fees.push(ReferralFees {
+                    mint,
+                    token_account,
+                    amount: state.base.amount,
+                }),
+                Err(e) => error!("Failed to unpack referral token account {token_account}: {e}"),
+            }
+        }
+        Ok(ReferralFeesReport {
+            referral_account: *referral_account,
+            fees,
+        })
+    }
+
+    /// The project a referral account belongs to, checking it is one
+    async fn referral_project_of(
+        &self,
+        referral_account: &Pubkey,
+    ) -> Result<Pubkey, ReferralError> {
+        let referral_program = self.referral_program();
+        let not_a_referral_account =
+            || ReferralError::NotAReferralAccount(*referral_account, referral_program);
+        let account = self
+            .solana_rpc
+            .get_account_with_commitment(referral_account, CommitmentConfig::confirmed())
+            .await?
+            .value
+            .filter(|account| account.owner == referral_program)
+            .ok_or_else(not_a_referral_account)?;
+        referral_account_project(&account.data).ok_or_else(not_a_referral_account)
+    }
+
+    /// Mints of tracked pools with their token programs
+    async fn traded_mints(&self) -> Result<BTreeMap<Pubkey, Pubkey>, ReferralError> {
+        let mut mints = BTreeMap::new();
+        for pool in self.accounts_service.pools() {
+            let pool_account = self.accounts_service.get_account(&pool).await?;
+            match PoolState::try_deserialize(&mut &pool_account[..]) {
+                Ok(pool_state) => {
+                    mints.insert(pool_state.token_0_mint, pool_state.token_0_program);
+                    mints.insert(pool_state.token_1_mint, pool_state.token_1_program);
+                }
+                Err(e) => error!("Failed to deserialize pool {}: {}", pool, e),
+            }
+        }
+        Ok(mints)
+    }
+
+    async fn get_multiple_accounts(
+        &self,
+        keys: &[Pubkey],
+    ) -> Result<Vec<Option<Account>>, ReferralError> {
+        let mut accounts = Vec::with_capacity(keys.len());
+        for chunk in keys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            accounts.extend(
+                self.solana_rpc
+                    .get_multiple_accounts_with_commitment(chunk, CommitmentConfig::confirmed())
+                    .await?
+                    .value,
+            );
+        }
+        Ok(accounts)
+    }
+
+    async fn latest_blockhash(&self) -> (Hash, u64) {
+        let blockhash = self.blockhash.read().await;
+        (blockhash.hash, blockhash.last_valid_block_height)
+    }
+}
+
+/// `initialize_referral_account_with_name`, creating `referral_account` named `name` under `project`
+fn initialize_referral_account_instruction(
+    referral_program: &Pubkey,
+    payer: &Pubkey,
+    partner: &Pubkey,
+    project: &Pubkey,
+    referral_account: &Pubkey,
+    name: &str,
+) -> Instruction {
+    let mut data = anchor_discriminator("initialize_referral_account_with_name").to_vec();
+    data.extend(borsh_string(name));
+    Instruction::new_with_bytes(
+        *referral_program,
+        &data,
+        vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*partner, false),
+            AccountMeta::new_readonly(*project, false),
+            AccountMeta::new(*referral_account, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+        ],
+    )
+}
+
+/// The project field of a referral account's data
+fn referral_account_project(data: &[u8]) -> Option<Pubkey> {
+    // Layout: discriminator, partner, project, ..
+    data.get(40..72)
+        .and_then(|project| Pubkey::try_from(project).ok())
+}
+
+/// Anchor's instruction discriminator: the first 8 bytes of `sha256("global:<name>")`
+fn anchor_discriminator(instruction_name: &str) -> [u8; 8] {
+    let hash = solana_sdk::hash::hash(format!("global:{instruction_name}").as_bytes());
+    let mut discriminator = [0; 8];
+    discriminator.copy_from_slice(&hash.to_bytes()[..8]);
+    discriminator
+}
+
+/// A borsh `String`: its length as a little-endian u32, then its bytes
+fn borsh_string(value: &str) -> Vec<u8> {
+    let mut bytes = (value.len() as u32).to_le_bytes().to_vec();
+    bytes.extend(value.as_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discriminators_match_the_referral_program() {
+        assert_eq!(
+            anchor_discriminator("initialize_referral_account_with_name"),
+            [241, 190, 107, 26, 244, 236, 119, 229]
+        );
+        assert_eq!(
+            anchor_discriminator("initialize_referral_token_account"),
+            [125, 18, 70, 95, 86, 179, 221, 190]
+        );
+    }
+
+    #[test]
+    fn strings_are_length_prefixed() {
+        assert_eq!(borsh_string(""), vec![0, 0, 0, 0]);
+        assert_eq!(borsh_string("gamma"), b"\x05\x00\x00\x00gamma".to_vec());
+    }
+
+    #[test]
+    fn reads_the_project_of_a_referral_account() {
+        let partner = Pubkey::new_unique();
+        let project = Pubkey::new_unique();
+        // ReferralAccount { partner, project, share_bps: u16, name: Option<String> }
+        let mut data = vec![237, 162, 80, 78, 196, 233, 91, 2];
+        data.extend(partner.to_bytes());
+        data.extend(project.to_bytes());
+        data.extend(5_000u16.to_le_bytes());
+        data.push(1);
+        data.extend(borsh_string("gamma"));
+
+        assert_eq!(referral_account_project(&data), Some(project));
+        assert_eq!(referral_account_project(&data[..71]), None);
+    }
+
+    #[test]
+    fn initialize_referral_account_takes_the_name_and_accounts_in_order() {
+        let [program, payer, partner, project, referral_account] =
+            [(); 5].map(|_| Pubkey::new_unique());
+        let instruction = initialize_referral_account_instruction(
+            &program,
+            &payer,
+            &partner,
+            &project,
+            &referral_account,
+            "gamma",
+        );
+
+        assert_eq!(instruction.program_id, program);
+        assert_eq!(
+            instruction.data[..8],
+            anchor_discriminator("initialize_referral_account_with_name")
+        );
+        assert_eq!(instruction.data[8..], borsh_string("gamma"));
+        assert_eq!(
+            instruction.accounts,
+            vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new_readonly(partner, false),
+                AccountMeta::new_readonly(project, false),
+                AccountMeta::new(referral_account, false),
+                AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+            ]
+        );
+    }
+}
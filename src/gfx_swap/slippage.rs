@@ -0,0 +1,294 @@
+use super::quote::{PairSnapshot, QuoteError};
+use crate::accounts::service::AccountsService;
+use crate::accounts::AccountsGetter;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+/// Cap on auto slippage when the client doesn't give one
+pub const DEFAULT_MAX_AUTO_SLIPPAGE_BPS: u16 = 300;
+/// Auto slippage never goes below this
+const MIN_AUTO_SLIPPAGE_BPS: u16 = 10;
+/// Headroom every auto slippage gets for price movement between quoting and landing
+const BASE_AUTO_SLIPPAGE_BPS: u64 = 30;
+/// How far back observations are considered for volatility
+const VOLATILITY_WINDOW_SECS: u64 = 300;
+/// Slippage added per pool update in the last minute. Busy pools move more before a swap lands.
+const ACTIVITY_BPS_PER_UPDATE: u64 = 2;
+/// Updates per minute beyond which activity adds no more slippage
+const MAX_COUNTED_UPDATES_PER_MINUTE: u64 = 60;
+/// How long pool updates are remembered for
+const ACTIVITY_WINDOW: Duration = Duration::from_secs(60);
+
+/// How auto slippage was chosen for a quote
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DynamicSlippageReport {
+    /// The slippage applied to the quote
+    pub slippage_bps: u16,
+    /// The heuristic's value before the min/max bounds were applied
+    pub heuristic_slippage_bps: u64,
+    /// Range of the pool's average price across recent observations, relative to its low
+    pub volatility_bps: u64,
+    /// Seconds of observations the volatility was measured over
+    pub volatility_window_secs: u64,
+    /// Pool account updates seen in the last minute
+    pub pool_updates_per_minute: u64,
+    /// The trade's input relative to the input reserve
+    pub trade_size_bps: u64,
+    pub max_auto_slippage_bps: u16,
+}
+
+/// Remembers when pools were last updated, as a measure of how actively they trade
+#[derive(Clone, Default)]
+pub struct PoolActivity {
+    updates: Arc<DashMap<Pubkey, VecDeque<Instant>>>,
+}
+
+impl PoolActivity {
+    fn record(&self, pool: Pubkey) {
+        let now = Instant::now();
+        let mut updates = self.updates.entry(pool).or_default();
+        while updates
+            .front()
+            .is_some_and(|update| now.duration_since(*update) > ACTIVITY_WINDOW)
+        {
+            updates.pop_front();
+        }
+        updates.push_back(now);
+    }
+
+    pub fn updates_per_minute(&self, pool: &Pubkey) -> u64 {
+        self.updates.get(pool).map_or(0, |updates| {
+            updates
+                .iter()
+                .filter(|update| update.elapsed() <= ACTIVITY_WINDOW)
+                .count() as u64
+        })
+    }
+}
+
+/// Tracks pool updates from the accounts store's change notifications
+pub fn start_pool_activity_task(
+    accounts_service: AccountsService,
+) -> (PoolActivity, JoinHandle<anyhow::Result<()>>) {
+    let activity = PoolActivity::default();
+    let task = tokio::task::spawn({
+        let activity = activity.clone();
+        async move {
+            let Some(mut updates) = accounts_service.subscribe_updates() else {
+                log::warn!(
+                    "Accounts store has no change notifications. Pool activity is not tracked"
+                );
+                return Ok(());
+            };
+            loop {
+                match updates.recv().await {
+                    Ok(pubkey) if accounts_service.is_pool(&pubkey) => activity.record(pubkey),
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(n)) => {
+                        log::warn!("Pool activity task missed {} account updates", n)
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                }
+            }
+        }
+    });
+
+    (activity, task)
+}
+
+impl PairSnapshot {
+    /// Recommends a slippage for trading `in_amount` from recent volatility, pool activity and
+    /// trade size, bounded by `max_slippage_bps`
+    pub fn dynamic_slippage(
+        &self,
+        in_amount: u64,
+        pool_updates_per_minute: u64,
+        max_slippage_bps: u16,
+    ) -> Result<DynamicSlippageReport, QuoteError> {
+        let now = u64::try_from(self.clock.unix_timestamp).unwrap_or_default();
+        let observations: Vec<(u64, u128)> = self
+            .observation_state
+            .observations
+            .iter()
+            .map(|observation| {
+                (
+                    observation.block_timestamp,
+                    observation.cumulative_token_0_price_x32,
+                )
+            })
+            .collect();
+        let (volatility_bps, volatility_window_secs) = observation_volatility_bps(
+            &observations,
+            usize::from(self.observation_state.observation_index),
+            now,
+        );
+        let (input_reserve, _) = self.reserves()?;
+        let trade_size_bps = (u128::from(in_amount) * 10_000)
+            .checked_div(u128::from(input_reserve))
+            .map_or(u64::MAX, |bps| u64::try_from(bps).unwrap_or(u64::MAX));
+
+        let (heuristic_slippage_bps, slippage_bps) = slippage_heuristic(
+            volatility_bps,
+            pool_updates_per_minute,
+            trade_size_bps,
+            max_slippage_bps,
+        );
+
+        Ok(DynamicSlippageReport {
+            slippage_bps,
+            heuristic_slippage_bps,
+            volatility_bps,
+            volatility_window_secs,
+            pool_updates_per_minute,
+            trade_size_bps,
+            max_auto_slippage_bps: max_slippage_bps,
+        })
+    }
+}
+
+/// The heuristic slippage and the slippage after bounds, both in bps
+fn slippage_heuristic(
+    volatility_bps: u64,
+    pool_updates_per_minute: u64,
+    trade_size_bps: u64,
+    max_slippage_bps: u16,
+) -> (u64, u16) {
+    // Volatility is how far the price has recently moved. Activity and trade size are how
+    // exposed this trade is to the next move: a busy pool gets more trades ahead of ours, and
+    // a large trade sits further out on the curve.
+    let activity_bps =
+        pool_updates_per_minute.min(MAX_COUNTED_UPDATES_PER_MINUTE) * ACTIVITY_BPS_PER_UPDATE;
+    let heuristic_slippage_bps = BASE_AUTO_SLIPPAGE_BPS
+        .saturating_add(volatility_bps)
+        .saturating_add(activity_bps)
+        .saturating_add(trade_size_bps / 2);
+    let slippage_bps = heuristic_slippage_bps.clamp(
+        u64::from(MIN_AUTO_SLIPPAGE_BPS),
+        u64::from(max_slippage_bps.max(MIN_AUTO_SLIPPAGE_BPS)),
+    );
+    (
+        heuristic_slippage_bps,
+        u16::try_from(slippage_bps).unwrap_or(max_slippage_bps),
+    )
+}
+
+/// The range of average token0 prices between consecutive observations in the last
+/// `VOLATILITY_WINDOW_SECS`, relative to the lowest. `observations` is the ring buffer as
+/// (block timestamp, cumulative token0 price) pairs. Returns the range and the seconds covered.
+fn observation_volatility_bps(
+    observations: &[(u64, u128)],
+    newest_index: usize,
+    now: u64,
+) -> (u64, u64) {
+    let since = now.saturating_sub(VOLATILITY_WINDOW_SECS);
+    let len = observations.len();
+    let (mut later_timestamp, mut later_cumulative) = observations[newest_index];
+    let mut low = u128::MAX;
+    let mut high = 0;
+    let mut window = 0;
+    for step in 1..len {
+        let (earlier_timestamp, earlier_cumulative) =
+            observations[(newest_index + len - step) % len];
+        if earlier_timestamp == 0 || earlier_timestamp >= later_timestamp || later_timestamp < since
+        {
+            break;
+        }
+        let seconds = later_timestamp - earlier_timestamp;
+        let average_price_x32 =
+            later_cumulative.wrapping_sub(earlier_cumulative) / u128::from(seconds);
+        low = low.min(average_price_x32);
+        high = high.max(average_price_x32);
+        window += seconds;
+        (later_timestamp, later_cumulative) = (earlier_timestamp, earlier_cumulative);
+    }
+
+    let volatility_bps = high
+        .saturating_sub(low)
+        .saturating_mul(10_000)
+        .checked_div(low)
+        .map_or(0, |bps| u64::try_from(bps).unwrap_or(u64::MAX));
+    (volatility_bps, window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRICE_X32: u128 = 1 << 32;
+
+    #[test]
+    fn steady_price_has_no_volatility() {
+        let observations = [(100, 0), (110, 10 * PRICE_X32), (120, 20 * PRICE_X32)];
+        assert_eq!(observation_volatility_bps(&observations, 2, 120), (0, 20));
+    }
+
+    #[test]
+    fn volatility_is_the_price_range_over_the_low() {
+        // Average price 1 over the first interval, then 1.25
+        let observations = [(100, 0), (104, 4 * PRICE_X32), (108, 9 * PRICE_X32)];
+        assert_eq!(
+            observation_volatility_bps(&observations, 2, 108),
+            (2_500, 8)
+        );
+    }
+
+    #[test]
+    fn volatility_ignores_observations_outside_the_window() {
+        let observations = [
+            (100, 0),
+            (500, 800 * PRICE_X32),
+            (510, 810 * PRICE_X32),
+            (0, 0),
+        ];
+        // The interval ending at 500 starts before the window and is the last one counted
+        assert_eq!(
+            observation_volatility_bps(&observations, 2, 510),
+            (10_000, 410)
+        );
+        // Once the later observation itself is out of the window the walk stops
+        assert_eq!(observation_volatility_bps(&observations, 2, 1_000), (0, 0));
+    }
+
+    #[test]
+    fn volatility_wraps_around_the_ring_buffer() {
+        let observations = [(120, 20 * PRICE_X32), (100, 0), (110, 10 * PRICE_X32)];
+        assert_eq!(observation_volatility_bps(&observations, 0, 120), (0, 20));
+    }
+
+    #[test]
+    fn heuristic_adds_up_its_components() {
+        assert_eq!(slippage_heuristic(20, 10, 100, 300), (120, 120));
+        // Activity stops counting past the cap
+        assert_eq!(
+            slippage_heuristic(0, 1_000, 0, 300),
+            (BASE_AUTO_SLIPPAGE_BPS + 120, 150)
+        );
+    }
+
+    #[test]
+    fn heuristic_is_bounded() {
+        assert_eq!(slippage_heuristic(10_000, 0, 0, 300), (10_030, 300));
+        assert_eq!(slippage_heuristic(u64::MAX, 0, 0, 300), (u64::MAX, 300));
+        // The max can't push slippage below the floor
+        assert_eq!(slippage_heuristic(0, 0, 0, 0), (30, MIN_AUTO_SLIPPAGE_BPS));
+    }
+
+    #[test]
+    fn activity_counts_recent_updates_per_pool() {
+        let activity = PoolActivity::default();
+        let pool = Pubkey::new_unique();
+        assert_eq!(activity.updates_per_minute(&pool), 0);
+        activity.record(pool);
+        activity.record(pool);
+        assert_eq!(activity.updates_per_minute(&pool), 2);
+        assert_eq!(activity.updates_per_minute(&Pubkey::new_unique()), 0);
+    }
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::VersionedTransaction;
+
+/// Most a sponsored swap can cost the sponsor when no limit is configured. 0.01 SOL.
+const DEFAULT_MAX_SPONSORED_LAMPORTS: u64 = 10_000_000;
+
+/// A keypair that pays fees and ATA rent for users holding no SOL
+#[derive(Clone)]
+pub struct Sponsor {
+    keypair: Arc<Keypair>,
+    /// Most one swap can cost the sponsor, in fees, tips and rent
+    pub max_lamports_per_swap: u64,
+}
+
+impl Sponsor {
+    pub fn from_file(path: &str, max_lamports_per_swap: Option<u64>) -> anyhow::Result<Self> {
+        let keypair = read_keypair_file(path)
+            .map_err(|e| anyhow!("Failed to read sponsor keypair {}: {}", path, e))?;
+        Ok(Sponsor {
+            keypair: Arc::new(keypair),
+            max_lamports_per_swap: max_lamports_per_swap.unwrap_or(DEFAULT_MAX_SPONSORED_LAMPORTS),
+        })
+    }
+
+    pub fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    /// Signs as fee payer, leaving the user's signature to the user
+    pub fn partially_sign(&self, transaction: &mut VersionedTransaction) {
+        let message = transaction.message.serialize();
+        let signers = usize::from(transaction.message.header().num_required_signatures);
+        if let Some(index) = transaction.message.static_account_keys()[..signers]
+            .iter()
+            .position(|key| *key == self.keypair.pubkey())
+        {
+            transaction.signatures[index] = self.keypair.sign_message(&message);
+        }
+    }
+}
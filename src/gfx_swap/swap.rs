@@ -1,21 +1,34 @@
+use super::pools::PoolStatus;
+use super::token_policy::TokenPolicyError;
+use super::token_policy::{associated_token_account_len, frozen_by_default};
+use super::transfer_hook::TransferLeg;
+use super::verify::QuoteVerificationError;
 use super::GfxSwapClient;
 use crate::accounts::{AccountsError, AccountsGetter};
+use crate::jito::MIN_TIP_LAMPORTS;
+use crate::priofee::Priority;
 use crate::utils::{derive_authority_pda, derive_pool_pda};
 
 use anchor_lang::prelude::AccountMeta;
 use anchor_lang::AccountDeserialize;
 use gamma::curve::TradeDirection;
 use gamma::states::{AmmConfig, PoolState};
-use rand::Rng;
+use serde::{Deserialize, Serialize};
 use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_sdk::account::Account;
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::hash::Hash;
 use solana_sdk::instruction::Instruction;
 use solana_sdk::message::{Message, VersionedMessage};
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::nonce::state::{State as NonceState, Versions as NonceVersions};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
 use solana_sdk::signature::Signature;
 use solana_sdk::transaction::VersionedTransaction;
-use solana_sdk::{pubkey, pubkey::Pubkey};
-use swap_api::quote::SwapMode;
+use spl_token_2022::extension::{memo_transfer, StateWithExtensions, StateWithExtensionsMut};
+use swap_api::quote::{QuoteResponse, SwapMode};
+use swap_api::serde_helpers::{field_as_string, option_field_as_string};
 use swap_api::swap::{
     SwapInstructionsResponse, SwapInstructionsResponseInternal, SwapRequest, SwapResponse,
 };
@@ -25,18 +38,20 @@ use swap_api::transaction_config::{
 use thiserror::Error;
 
 /// Protocol defined: The default compute units set for a transaction
-const DEFAULT_INSTRUCTION_COMPUTE_UNIT: u32 = 200_000;
+pub const DEFAULT_INSTRUCTION_COMPUTE_UNIT: u32 = 200_000;
+/// Protocol defined: The most compute units a transaction can use
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
 /// Protocol defined: There are 10^6 micro-lamports in one lamport
 const MICRO_LAMPORTS_PER_LAMPORT: u64 = 1_000_000;
 /// The cap we set on auto priority-fees
 const MAX_AUTO_PRIORITY_FEE_LAMPORTS: u64 = 5_000_000;
-/// ID of the referral program
-const REFERRAL_PROGRAM_MAINNET: Pubkey = pubkey!("REFER4ZgmyYx9c6He5XfaTMiGfdLwRnkV4RPp9t9iF3");
 
 /// Make sure the cu-price used is at least this value in micro-lamports
-const MIN_CU_PRICE: u64 = 20_000;
+pub const MIN_CU_PRICE: u64 = 20_000;
 /// Make sure the priority-fee used is at least this value in lamports
 const MIN_PRIORITY_FEE: u64 = 20_000;
+/// Protocol defined: The base fee per transaction signature
+const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
 
 #[derive(Debug, Error)]
 pub enum SwapError {
@@ -54,41 +69,476 @@ pub enum SwapError {
     SerializeTxn(#[from] bincode::Error),
     #[error("Prioritization fee calculation resulted in overflow")]
     PrioritizationFeeOverflow,
+    #[error("Failed to resolve transfer-hook accounts: {0}")]
+    TransferHook(String),
+    #[error(transparent)]
+    TokenPolicy(#[from] TokenPolicyError),
+    #[error("No pool exists for this input-mint - output-mint pair")]
+    PairNotTradeable,
+    #[error("Swaps are disabled for this pool")]
+    PoolDisabled,
+    #[error("Pool is not open for swaps until unix timestamp {opens_at}")]
+    PoolNotOpen { opens_at: u64 },
+    #[error("Quote rejected: {0}")]
+    QuoteVerification(#[from] QuoteVerificationError),
+    #[error("Unknown quote id {0}. It may have been evicted, post the full quote instead")]
+    UnknownQuote(String),
+    #[error("Quote expired at slot {expiry_slot}, current slot is {current_slot}")]
+    QuoteExpired { expiry_slot: u64, current_slot: u64 },
+    #[error("Quote signature doesn't match the quote")]
+    InvalidQuoteSignature,
+    #[error("Invalid nonce account: {0}")]
+    InvalidNonceAccount(String),
+    #[error("Sponsored swaps are not enabled")]
+    SponsorUnavailable,
+    #[error("Swap would cost the sponsor {required} lamports, more than the {limit} allowed")]
+    SponsorLimitExceeded { required: u64, limit: u64 },
+    #[error("Jito tips are unavailable: {0}")]
+    JitoUnavailable(String),
+    #[error("Insufficient {mint} balance: {required} required, {available} available")]
+    InsufficientBalance {
+        mint: Pubkey,
+        required: u64,
+        available: u64,
+    },
+    #[error("Input token account {0} is frozen")]
+    InputAccountFrozen(Pubkey),
+    #[error("Invalid destination token account: {0}")]
+    InvalidDestinationAccount(String),
+    #[error("Referral account {0} is not allowed")]
+    ReferralNotAllowed(Pubkey),
+}
+
+/// A jupiter-compatible `SwapRequest` that can reference a quote issued by this server by its
+/// `quoteId` instead of posting it in full
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GammaSwapRequest {
+    #[serde(with = "field_as_string")]
+    pub user_public_key: Pubkey,
+    pub quote_response: Option<PostedQuote>,
+    pub quote_id: Option<String>,
+    /// Read here rather than by `config` so `priorityLevelWithMaxLamports` and an `auto` Jito tip
+    /// are accepted
+    pub prioritization_fee_lamports: Option<GammaPrioritizationFeeLamports>,
+    /// Durable nonce to build the transaction on instead of a recent blockhash
+    #[serde(default, with = "option_field_as_string")]
+    pub nonce_account: Option<Pubkey>,
+    /// Signer allowed to advance the nonce. Defaults to the user.
+    #[serde(default, with = "option_field_as_string")]
+    pub nonce_authority: Option<Pubkey>,
+    /// Pays transaction fees and ATA rent instead of the user, and signs as well
+    #[serde(default, with = "option_field_as_string")]
+    pub fee_payer: Option<Pubkey>,
+    /// Have the server's sponsor keypair pay and partially sign
+    #[serde(default)]
+    pub sponsored: bool,
+    /// Check balances and token accounts before building. Defaults to the server's setting.
+    #[serde(default)]
+    pub preflight_checks: Option<bool>,
+    /// Referral account to share fees with instead of the server's. Has to be allowed.
+    #[serde(default, with = "option_field_as_string")]
+    pub referral_account: Option<Pubkey>,
+    #[serde(flatten)]
+    pub config: TransactionConfig,
+}
+
+/// `prioritizationFeeLamports` as jupiter's API accepts it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum GammaPrioritizationFeeLamports {
+    #[serde(rename_all = "camelCase")]
+    PriorityLevelWithMaxLamports {
+        priority_level_with_max_lamports: PriorityLevelWithMaxLamports,
+    },
+    #[serde(rename_all = "camelCase")]
+    AutoJitoTip {
+        jito_tip_lamports: AutoTip,
+    },
+    Jupiter(PrioritizationFeeLamports),
+}
+
+/// `"auto"`: tip what recently landed bundles did
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AutoTip {
+    Auto,
+}
+
+/// Prioritization fees jupiter's `TransactionConfig` can't express
+#[derive(Debug, Clone, Copy)]
+pub enum ExtendedPrioritization {
+    PriorityLevelWithMaxLamports(PriorityLevelWithMaxLamports),
+    /// A Jito tip from the tip floor, capped like other auto fees
+    AutoJitoTip,
+}
+
+/// A fee at a percentile of the pool's recent fees, capped at `max_lamports`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PriorityLevelWithMaxLamports {
+    pub priority_level: PriorityLevel,
+    pub max_lamports: u64,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PriorityLevel {
+    Medium,
+    High,
+    VeryHigh,
+}
+
+impl PriorityLevel {
+    fn fee(self, priority: &Priority) -> u64 {
+        match self {
+            PriorityLevel::Medium => priority.medium,
+            PriorityLevel::High => priority.high,
+            PriorityLevel::VeryHigh => priority.extreme,
+        }
+    }
+}
+
+/// A swap request with its quote resolved
+#[derive(Debug, Clone)]
+pub struct ResolvedSwapRequest {
+    pub request: SwapRequest,
+    /// Set instead of the request's `prioritization_fee_lamports` when the client asked for it
+    pub extended_prioritization: Option<ExtendedPrioritization>,
+    pub durable_nonce: Option<DurableNonce>,
+    /// Pays fees and ATA rent. The user unless the request says otherwise.
+    pub fee_payer: Pubkey,
+    /// Whether the fee payer is the server's sponsor
+    pub sponsored: bool,
+    pub preflight_checks: bool,
+    /// Shares the trade fee, if its token account for the input mint exists
+    pub referral_account: Option<Pubkey>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DurableNonce {
+    pub account: Pubkey,
+    pub authority: Pubkey,
+}
+
+/// Jupiter's `SwapResponse` with what the transaction pays to be prioritized
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GammaSwapResponse {
+    #[serde(flatten)]
+    pub swap_response: SwapResponse,
+    /// Priority fee, or the Jito tip, in lamports
+    pub prioritization_fee_lamports: u64,
+    pub compute_unit_limit: u32,
+    /// Whether the transaction uses a durable nonce. Such transactions don't expire, so
+    /// `lastValidBlockHeight` is `u64::MAX`.
+    pub non_expiring: bool,
+    #[serde(with = "field_as_string")]
+    pub fee_payer: Pubkey,
+    /// Whether the transaction already carries the sponsor's signature
+    pub sponsored: bool,
+    /// Rent the fee payer puts into token accounts the transaction creates. Zero when the
+    /// user's token accounts already exist.
+    pub ata_rent_lamports: u64,
+}
+
+/// Jupiter's swap instructions with the rent their setup instructions cost
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GammaSwapInstructionsResponse {
+    #[serde(flatten)]
+    pub instructions: SwapInstructionsResponseInternal,
+    /// Rent the fee payer puts into token accounts the setup instructions create
+    pub ata_rent_lamports: u64,
+}
+
+/// Swap instructions and what they pay to be prioritized
+struct SwapInstructions {
+    instructions: SwapInstructionsResponse,
+    prioritization_fee_lamports: u64,
+    compute_unit_limit: u32,
+    /// Rent the fee payer puts into created token accounts
+    ata_rent_lamports: u64,
+}
+
+/// How a swap pays to be prioritized
+enum Prioritization {
+    /// Compute-unit price in micro-lamports
+    CuPrice(u64),
+    /// Transfer to a Jito tip account in lamports
+    JitoTip(u64),
+}
+
+/// A quote posted back in full. Quotes from this server carry the ID and signature they were
+/// issued with, which are checked if present.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostedQuote {
+    #[serde(flatten)]
+    pub quote: QuoteResponse,
+    pub quote_id: Option<String>,
+    pub expiry_slot: Option<u64>,
+    pub signature: Option<String>,
 }
 
 impl GfxSwapClient {
+    /// Turns a swap request into one with the full quote, checking a referenced or signed quote
+    /// was issued by this server and hasn't expired
+    pub async fn resolve_swap_request(
+        &self,
+        req: GammaSwapRequest,
+    ) -> Result<ResolvedSwapRequest, SwapError> {
+        let GammaSwapRequest {
+            user_public_key,
+            quote_response,
+            quote_id,
+            prioritization_fee_lamports,
+            nonce_account,
+            nonce_authority,
+            fee_payer,
+            sponsored,
+            preflight_checks,
+            referral_account,
+            mut config,
+        } = req;
+        let (quote_response, signed) = match (quote_response, quote_id) {
+            (None, Some(quote_id)) => {
+                let cached = self
+                    .quote_cache
+                    .get(&quote_id)
+                    .ok_or_else(|| SwapError::UnknownQuote(quote_id.clone()))?;
+                (cached.quote, Some((quote_id, cached.expiry_slot)))
+            }
+            (Some(posted), None) => match (posted.quote_id, posted.expiry_slot, posted.signature) {
+                (Some(quote_id), Some(expiry_slot), Some(signature)) => {
+                    if !self
+                        .quote_cache
+                        .verify(&quote_id, expiry_slot, &posted.quote, &signature)
+                    {
+                        return Err(SwapError::InvalidQuoteSignature);
+                    }
+                    (posted.quote, Some((quote_id, expiry_slot)))
+                }
+                // Unsigned quotes are only subject to quote verification
+                _ => (posted.quote, None),
+            },
+            (Some(_), Some(_)) => {
+                return Err(SwapError::InvalidRequest(
+                    "Specify either quoteResponse or quoteId, not both".to_string(),
+                ))
+            }
+            (None, None) => {
+                return Err(SwapError::InvalidRequest(
+                    "One of quoteResponse or quoteId is required".to_string(),
+                ))
+            }
+        };
+
+        if let Some((quote_id, expiry_slot)) = signed {
+            let current_slot = self.get_cluster_clock().await?.slot;
+            if current_slot > expiry_slot {
+                return Err(SwapError::QuoteExpired {
+                    expiry_slot,
+                    current_slot,
+                });
+            }
+            // Quote staleness at execution: how long clients hold quotes before swapping
+            let age = self
+                .quote_cache
+                .get(&quote_id)
+                .map(|cached| format!("{}ms", cached.issued_at.elapsed().as_millis()))
+                .unwrap_or_else(|| "unknown".to_string());
+            log::info!(
+                "Quote {} converted to swap. quote-slot={}, current-slot={}, age={}",
+                quote_id,
+                quote_response.context_slot,
+                current_slot,
+                age
+            );
+        }
+
+        let extended_prioritization = match prioritization_fee_lamports {
+            Some(GammaPrioritizationFeeLamports::PriorityLevelWithMaxLamports {
+                priority_level_with_max_lamports,
+            }) => Some(ExtendedPrioritization::PriorityLevelWithMaxLamports(
+                priority_level_with_max_lamports,
+            )),
+            Some(GammaPrioritizationFeeLamports::AutoJitoTip {
+                jito_tip_lamports: AutoTip::Auto,
+            }) => Some(ExtendedPrioritization::AutoJitoTip),
+            Some(GammaPrioritizationFeeLamports::Jupiter(prioritization_fee_lamports)) => {
+                config.prioritization_fee_lamports = Some(prioritization_fee_lamports);
+                None
+            }
+            None => None,
+        };
+
+        let fee_payer = match (fee_payer, sponsored, &self.sponsor) {
+            (Some(_), true, _) => {
+                return Err(SwapError::InvalidRequest(
+                    "Specify either feePayer or sponsored, not both".to_string(),
+                ))
+            }
+            (None, true, Some(sponsor)) => sponsor.pubkey(),
+            (None, true, None) => return Err(SwapError::SponsorUnavailable),
+            (fee_payer, false, _) => fee_payer.unwrap_or(user_public_key),
+        };
+
+        Ok(ResolvedSwapRequest {
+            fee_payer,
+            sponsored,
+            preflight_checks: preflight_checks.unwrap_or(self.preflight_checks),
+            referral_account: match referral_account {
+                Some(referral_account) if !self.is_referral_allowed(&referral_account) => {
+                    return Err(SwapError::ReferralNotAllowed(referral_account))
+                }
+                Some(referral_account) => Some(referral_account),
+                None => self.referral,
+            },
+            request: SwapRequest {
+                user_public_key,
+                quote_response,
+                config,
+            },
+            extended_prioritization,
+            durable_nonce: match (nonce_account, nonce_authority) {
+                (Some(account), authority) => Some(DurableNonce {
+                    account,
+                    authority: authority.unwrap_or(user_public_key),
+                }),
+                (None, Some(_)) => {
+                    return Err(SwapError::InvalidRequest(
+                        "nonceAuthority requires a nonceAccount".to_string(),
+                    ))
+                }
+                (None, None) => None,
+            },
+        })
+    }
+
     pub async fn swap_instructions(
         &self,
-        req: &SwapRequest,
-    ) -> Result<SwapInstructionsResponseInternal, SwapError> {
-        Ok(self.swap_instructions_inner(req).await?.into())
+        req: &ResolvedSwapRequest,
+    ) -> Result<GammaSwapInstructionsResponse, SwapError> {
+        if req.durable_nonce.is_some() {
+            // The advance instruction has to come first, which the instructions response can't
+            // express
+            return Err(SwapError::InvalidRequest(
+                "Durable nonces are only supported by /swap".to_string(),
+            ));
+        }
+        if req.sponsored {
+            // The sponsor only signs transactions it has checked
+            return Err(SwapError::InvalidRequest(
+                "Sponsored swaps are only supported by /swap".to_string(),
+            ));
+        }
+        let SwapInstructions {
+            instructions,
+            ata_rent_lamports,
+            ..
+        } = self.swap_instructions_inner(req).await?;
+        Ok(GammaSwapInstructionsResponse {
+            instructions: instructions.into(),
+            ata_rent_lamports,
+        })
     }
 
-    pub async fn swap_transaction(&self, req: &SwapRequest) -> Result<SwapResponse, SwapError> {
-        let blockhash_update = self.blockhash.read().await;
-        let instructions = self.swap_instructions_inner(req).await?;
-        let transaction = build_transaction(
+    pub async fn swap_transaction(
+        &self,
+        req: &ResolvedSwapRequest,
+    ) -> Result<GammaSwapResponse, SwapError> {
+        let SwapInstructions {
+            instructions,
+            prioritization_fee_lamports,
+            compute_unit_limit,
+            ata_rent_lamports,
+        } = self.swap_instructions_inner(req).await?;
+        let (blockhash, last_valid_block_height, advance_nonce) = match &req.durable_nonce {
+            Some(durable_nonce) => (
+                self.durable_nonce_value(durable_nonce).await?,
+                u64::MAX,
+                Some(solana_sdk::system_instruction::advance_nonce_account(
+                    &durable_nonce.account,
+                    &durable_nonce.authority,
+                )),
+            ),
+            None => {
+                let blockhash_update = self.blockhash.read().await;
+                (
+                    blockhash_update.hash,
+                    blockhash_update.last_valid_block_height,
+                    None,
+                )
+            }
+        };
+        let mut transaction = build_transaction(
             instructions,
-            Some(&req.user_public_key),
-            Some(blockhash_update.hash),
+            Some(&req.fee_payer),
+            Some(blockhash),
+            advance_nonce,
         );
+        if req.sponsored {
+            let sponsor = self.sponsor.as_ref().ok_or(SwapError::SponsorUnavailable)?;
+            // A jito tip is the prioritization fee, and is paid by the sponsor too
+            let required = LAMPORTS_PER_SIGNATURE
+                .saturating_mul(u64::from(
+                    transaction.message.header().num_required_signatures,
+                ))
+                .saturating_add(prioritization_fee_lamports)
+                .saturating_add(ata_rent_lamports);
+            if required > sponsor.max_lamports_per_swap {
+                return Err(SwapError::SponsorLimitExceeded {
+                    required,
+                    limit: sponsor.max_lamports_per_swap,
+                });
+            }
+            log::info!(
+                "Sponsoring swap for {}: up to {} SOL",
+                req.request.user_public_key,
+                required as f64 / LAMPORTS_PER_SOL as f64
+            );
+            sponsor.partially_sign(&mut transaction);
+        }
 
-        Ok(SwapResponse {
-            swap_transaction: bincode::serialize(&transaction)?,
-            last_valid_block_height: blockhash_update.last_valid_block_height,
+        Ok(GammaSwapResponse {
+            swap_response: SwapResponse {
+                swap_transaction: bincode::serialize(&transaction)?,
+                last_valid_block_height,
+            },
+            prioritization_fee_lamports,
+            compute_unit_limit,
+            non_expiring: req.durable_nonce.is_some(),
+            fee_payer: req.fee_payer,
+            sponsored: req.sponsored,
+            ata_rent_lamports,
         })
     }
 
+    /// The nonce a durable-nonce transaction has to be built on. Nonces change with every use,
+    /// so the account is read from the cluster on every request instead of being tracked.
+    async fn durable_nonce_value(&self, durable_nonce: &DurableNonce) -> Result<Hash, SwapError> {
+        let account = self
+            .solana_rpc
+            .get_account_with_commitment(&durable_nonce.account, CommitmentConfig::confirmed())
+            .await?
+            .value
+            .ok_or_else(|| {
+                SwapError::InvalidNonceAccount(format!("{} does not exist", durable_nonce.account))
+            })?;
+        nonce_blockhash(durable_nonce, &account)
+    }
+
     async fn swap_instructions_inner(
         &self,
-        req: &SwapRequest,
-    ) -> Result<SwapInstructionsResponse, SwapError> {
+        resolved: &ResolvedSwapRequest,
+    ) -> Result<SwapInstructions, SwapError> {
         // Currently ignored:
         // - as-legacy-transaction. Legacy is the default since we only deal with a single swap route
         // - use-shared-accounts
         // - use-token-ledger
         // - fee-account
-        // - PrioritizationFeeLamports::Auto, PrioritizationFeeLamports::AutoMultiplier
+        let req = &resolved.request;
 
         if req.quote_response.input_mint == req.quote_response.output_mint {
             return Err(SwapError::InvalidRequest(
@@ -127,8 +577,23 @@ impl GfxSwapClient {
             &token_1_mint,
             &self.gamma_program_id,
         );
-        let pool_account = self.accounts_service.get_account(&pool).await?;
+        let pool_account = match self.accounts_service.get_account(&pool).await {
+            Ok(account) => account,
+            Err(AccountsError::NotFound) => return Err(SwapError::PairNotTradeable),
+            Err(e) => return Err(e.into()),
+        };
         let pool_state = PoolState::try_deserialize(&mut &pool_account[..])?;
+        let clock = self.get_cluster_clock().await?;
+        let pool_status = PoolStatus::new(&pool_state, clock.unix_timestamp);
+        if !pool_status.swap_enabled {
+            return Err(SwapError::PoolDisabled);
+        }
+        if !pool_status.is_open {
+            return Err(SwapError::PoolNotOpen {
+                opens_at: pool_status.open_time,
+            });
+        }
+        self.verify_quote(&req.quote_response, clock).await?;
         let config_account = self
             .accounts_service
             .get_account(&self.gamma_config)
@@ -166,6 +631,18 @@ impl GfxSwapClient {
             ),
         };
 
+        let input_mint_account = self.accounts_service.get_account(&input_token_mint).await?;
+        let output_mint_account = self
+            .accounts_service
+            .get_account(&output_token_mint)
+            .await?;
+        self.token_policy
+            .check_mint(&input_token_mint, &input_mint_account)?;
+        self.token_policy
+            .check_mint(&output_token_mint, &output_mint_account)?;
+        // Created ATAs are funded by the fee payer
+        let mut ata_rent_lamports = 0;
+
         let input_ata = spl_associated_token_account::get_associated_token_address_with_program_id(
             &req.user_public_key,
             &req.quote_response.input_mint,
@@ -176,20 +653,95 @@ impl GfxSwapClient {
             &req.quote_response.output_mint,
             &output_token_program,
         );
+        let input_token_account = input_ata;
+        let output_token_account = destination_token_account.unwrap_or(output_ata);
+        let base_in = match req.quote_response.swap_mode {
+            SwapMode::ExactIn => true,
+            SwapMode::ExactOut => false,
+        };
+        let (max_amount_in, min_amount_out) = if base_in {
+            (
+                req.quote_response.in_amount,
+                req.quote_response.other_amount_threshold,
+            )
+        } else {
+            (
+                req.quote_response.other_amount_threshold,
+                req.quote_response.out_amount,
+            )
+        };
+        let referral = resolved.referral_account.map(|referral_account| {
+            (
+                referral_account,
+                self.referral_token_account(&referral_account, &input_token_mint),
+            )
+        });
+        // One lookup tells which token accounts need creating, whether the destination requires
+        // memos, whether the referral can collect fees, and what the pre-flight checks need
+        let lookup_accounts: Vec<Pubkey> = [
+            input_token_account,
+            output_token_account,
+            req.user_public_key,
+        ]
+        .into_iter()
+        .chain(referral.map(|(_, referral_token_account)| referral_token_account))
+        .collect();
+        let mut token_accounts = self
+            .solana_rpc
+            .get_multiple_accounts_with_commitment(&lookup_accounts, CommitmentConfig::confirmed())
+            .await?
+            .value
+            .into_iter();
+        let input_account = token_accounts.next().flatten();
+        let output_account = token_accounts.next().flatten();
+        let user_account = token_accounts.next().flatten();
+        let referral_token_account_exists = token_accounts.next().flatten().is_some();
+        let wraps_sol =
+            *wrap_and_unwrap_sol && req.quote_response.input_mint == spl_token::native_mint::ID;
+        if resolved.preflight_checks {
+            // Wrapped SOL is checked against the user's SOL once the fees are known
+            if !wraps_sol {
+                check_input_token_account(
+                    &input_token_account,
+                    input_account.as_ref(),
+                    &input_token_mint,
+                    max_amount_in,
+                )?;
+            }
+            if let Some(destination_token_account) = destination_token_account {
+                check_destination_token_account(
+                    destination_token_account,
+                    output_account.as_ref(),
+                    &output_token_mint,
+                    &output_token_program,
+                )?;
+            }
+        }
+        // Durable-nonce transactions can land long after they're built, by when an account that
+        // exists now may have been closed
+        let exists =
+            |account: &Option<Account>| account.is_some() && resolved.durable_nonce.is_none();
 
         if req.quote_response.input_mint == spl_token::native_mint::ID {
             // Only create an input-ata if it's the native mint
-            let create_ata_ix =
-                spl_associated_token_account::instruction::create_associated_token_account_idempotent(
-                    &req.user_public_key,
-                    &req.user_public_key,
-                    &req.quote_response.input_mint,
+            if !exists(&input_account) {
+                let create_ata_ix =
+                    spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                        &resolved.fee_payer,
+                        &req.user_public_key,
+                        &req.quote_response.input_mint,
+                        &input_token_program,
+                    );
+                setup_instructions.push(create_ata_ix);
+                ata_rent_lamports += Rent::default().minimum_balance(associated_token_account_len(
+                    &input_token_mint,
+                    &input_mint_account,
                     &input_token_program,
-                );
-            setup_instructions.push(create_ata_ix);
+                )?);
+            }
 
             // Only wrap SOL if user specifies this behaviour and the input-token is SOL
-            if *wrap_and_unwrap_sol {
+            if wraps_sol {
                 let transfer_ix = solana_sdk::system_instruction::transfer(
                     &req.user_public_key,
                     &input_ata,
@@ -214,14 +766,27 @@ impl GfxSwapClient {
         if destination_token_account.is_none() {
             // Only create an ATA if no destination-token-account is specified. If specified, we assume it is
             // already initialized.
-            let create_ata_ix =
-                spl_associated_token_account::instruction::create_associated_token_account_idempotent(
-                    &req.user_public_key,
-                    &req.user_public_key,
-                    &req.quote_response.output_mint,
+            if !exists(&output_account) {
+                // An existing account was thawed already, so only a new one would be frozen
+                if output_account.is_none()
+                    && frozen_by_default(&output_token_mint, &output_mint_account)?
+                {
+                    return Err(TokenPolicyError::FrozenByDefault(output_token_mint).into());
+                }
+                let create_ata_ix =
+                    spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                        &resolved.fee_payer,
+                        &req.user_public_key,
+                        &req.quote_response.output_mint,
+                        &output_token_program,
+                    );
+                setup_instructions.push(create_ata_ix);
+                ata_rent_lamports += Rent::default().minimum_balance(associated_token_account_len(
+                    &output_token_mint,
+                    &output_mint_account,
                     &output_token_program,
-                );
-            setup_instructions.push(create_ata_ix);
+                )?);
+            }
 
             if *wrap_and_unwrap_sol && req.quote_response.output_mint == spl_token::native_mint::ID
             {
@@ -238,12 +803,16 @@ impl GfxSwapClient {
             }
         }
 
-        let input_token_account = input_ata;
-        let output_token_account = destination_token_account.unwrap_or(output_ata);
-        let base_in = match req.quote_response.swap_mode {
-            SwapMode::ExactIn => true,
-            SwapMode::ExactOut => false,
-        };
+        // Token-2022 looks for the memo in the instruction processed right before the transfer at
+        // the same stack height. Gamma's transfer into the destination is a CPI, so that would
+        // have to be a memo Gamma itself invokes. A top-level memo before the swap is the swap
+        // instruction's sibling, not the transfer's, and doesn't count.
+        if output_token_program == spl_token_2022::ID && requires_incoming_memo(output_account) {
+            return Err(SwapError::InvalidDestinationAccount(format!(
+                "{} requires incoming transfer memos, which swaps can't provide",
+                output_token_account
+            )));
+        }
 
         let mut accounts = anchor_lang::ToAccountMetas::to_account_metas(
             &gamma::accounts::Swap {
@@ -263,24 +832,49 @@ impl GfxSwapClient {
             },
             None,
         );
-        if let Some(referral_account) = self.referral {
-            let referral_program = self.referral_program.unwrap_or(REFERRAL_PROGRAM_MAINNET);
-            let referral_token_account = Pubkey::find_program_address(
-                &[
-                    b"referral_ata",
-                    referral_account.as_ref(),
-                    input_token_mint.as_ref(),
-                ],
-                &referral_program,
-            )
-            .0;
-            accounts.extend([
-                AccountMeta::new_readonly(gamma::ID, false),
-                AccountMeta::new_readonly(gamma::ID, false),
-                AccountMeta::new_readonly(referral_account, false),
-                AccountMeta::new(referral_token_account, false),
-            ]);
-        }
+        let referral = match referral {
+            Some((referral_account, referral_token_account)) if referral_token_account_exists => {
+                Some((referral_account, referral_token_account))
+            }
+            // The swap would fail on the missing account, so it goes without the referral
+            Some((referral_account, referral_token_account)) => {
+                log::debug!(
+                    "Referral token account {} of {} doesn't exist, swapping without referral",
+                    referral_token_account,
+                    referral_account
+                );
+                None
+            }
+            None => None,
+        };
+
+        // Token-2022 mints with a transfer-hook need the hook program's extra accounts passed
+        // through to the transfer CPIs
+        let input_hook_accounts = self
+            .resolve_transfer_hook_accounts(TransferLeg {
+                source: input_token_account,
+                mint: input_token_mint,
+                destination: input_vault,
+                authority: req.user_public_key,
+                token_program: input_token_program,
+                amount: max_amount_in,
+            })
+            .await?;
+        let output_hook_accounts = self
+            .resolve_transfer_hook_accounts(TransferLeg {
+                source: output_vault,
+                mint: output_token_mint,
+                destination: output_token_account,
+                authority: derive_authority_pda(&self.gamma_program_id).0,
+                token_program: output_token_program,
+                amount: min_amount_out,
+            })
+            .await?;
+        accounts.extend(swap_remaining_accounts(
+            referral,
+            input_hook_accounts,
+            output_hook_accounts,
+        ));
 
         let data = if base_in {
             anchor_lang::InstructionData::data(&gamma::instruction::SwapBaseInput {
@@ -306,7 +900,7 @@ impl GfxSwapClient {
         let dynamic_compute =
             if req.config.dynamic_compute_unit_limit {
                 let simulate_txn =
-                    build_transaction(instructions.clone(), Some(&req.user_public_key), None);
+                    build_transaction(instructions.clone(), Some(&resolved.fee_payer), None, None);
                 let result = self
                     .solana_rpc
                     .simulate_transaction_with_config(
@@ -328,51 +922,102 @@ impl GfxSwapClient {
 
         if let Some(compute_units) = dynamic_compute {
             log::trace!("setting dynamic compute unit: {}", compute_units);
-            instructions.compute_budget_instructions.push(
-                solana_sdk::compute_budget::ComputeBudgetInstruction::set_compute_unit_limit(
-                    compute_units,
-                ),
-            );
         }
 
-        let compute_units = dynamic_compute.unwrap_or(DEFAULT_INSTRUCTION_COMPUTE_UNIT);
+        // A Jito tip is a transfer added to the setup instructions below
+        let tips = matches!(
+            (
+                compute_unit_price_micro_lamports,
+                prioritization_fee_lamports,
+                resolved.extended_prioritization
+            ),
+            (None, Some(PrioritizationFeeLamports::JitoTipLamports(_)), _)
+                | (None, None, Some(ExtendedPrioritization::AutoJitoTip))
+        );
+        let instruction_count = usize::from(resolved.durable_nonce.is_some())
+            + instructions.setup_instructions.len()
+            + usize::from(tips)
+            + 1
+            + usize::from(instructions.cleanup_instruction.is_some());
+        let compute_units =
+            dynamic_compute.unwrap_or_else(|| default_compute_unit_limit(instruction_count));
+        // Swaps write-lock the pool state and both vaults, so fees paid for those are what this
+        // transaction competes with
+        let pool_writable_accounts = [pool, pool_state.token_0_vault, pool_state.token_1_vault];
         log::debug!("Compute unit price microlamports: {compute_unit_price_micro_lamports:#?}");
         log::debug!("Prioritization fee lamports: {prioritization_fee_lamports:#?}");
-        match (
+        let prioritization = match (
             compute_unit_price_micro_lamports,
             prioritization_fee_lamports,
+            resolved.extended_prioritization,
         ) {
-            (Some(ComputeUnitPriceMicroLamports::MicroLamports(cu_price)), _) => {
+            (Some(ComputeUnitPriceMicroLamports::MicroLamports(cu_price)), _, _) => {
                 log::trace!("setting user defined cu-price: {}", cu_price);
-                let compute_ix =
-                    solana_sdk::compute_budget::ComputeBudgetInstruction::set_compute_unit_price(
-                        *cu_price,
-                    );
-                instructions.compute_budget_instructions.push(compute_ix);
+                Prioritization::CuPrice(*cu_price)
+            }
+            (
+                None,
+                None,
+                Some(ExtendedPrioritization::PriorityLevelWithMaxLamports(
+                    PriorityLevelWithMaxLamports {
+                        priority_level,
+                        max_lamports,
+                    },
+                )),
+            ) => {
+                let priofee = match &self.priofees_handle {
+                    Some(handle) => std::cmp::min(
+                        std::cmp::max(
+                            MIN_PRIORITY_FEE,
+                            priority_level.fee(
+                                &handle
+                                    .get_pool_priofee(&pool, &pool_writable_accounts)
+                                    .await
+                                    .per_transaction,
+                            ),
+                        ),
+                        max_lamports,
+                    ),
+                    None => max_lamports,
+                };
+                let cu_price = calculate_cu_price(priofee, compute_units);
+                log::trace!(
+                    "priority-level: cu-price={}, level={:?}, priofee={}, max-lamports={}, cu-limit={}",
+                    cu_price,
+                    priority_level,
+                    priofee,
+                    max_lamports,
+                    compute_units
+                );
+                Prioritization::CuPrice(cu_price)
             }
-            (Some(ComputeUnitPriceMicroLamports::Auto), _) | (None, None) => {
+            (Some(ComputeUnitPriceMicroLamports::Auto), _, _) | (None, None, None) => {
                 let cu_price = match &self.priofees_handle {
                     Some(handle) => std::cmp::max(
                         MIN_CU_PRICE,
-                        handle.get_latest_priofee().await.per_compute_unit.medium,
+                        handle
+                            .get_pool_priofee(&pool, &pool_writable_accounts)
+                            .await
+                            .per_compute_unit
+                            .medium,
                     ),
                     None => MIN_CU_PRICE,
                 };
                 log::trace!("cu-price-microlamports: cu-price={}", cu_price);
-                let compute_ix =
-                    solana_sdk::compute_budget::ComputeBudgetInstruction::set_compute_unit_price(
-                        cu_price,
-                    );
-                instructions.compute_budget_instructions.push(compute_ix);
+                Prioritization::CuPrice(cu_price)
             }
-            (None, Some(PrioritizationFeeLamports::Auto)) => {
+            (None, Some(PrioritizationFeeLamports::Auto), _) => {
                 // protocol: priority-fee = cu-price * cu-limit / 1_000_000
                 // agave: priority-fee = (cu-price * cu-limit + 999_999) / 1_000_000
                 let priofee = match &self.priofees_handle {
                     Some(handle) => {
                         let priofee = std::cmp::max(
                             MIN_PRIORITY_FEE,
-                            handle.get_latest_priofee().await.per_transaction.medium,
+                            handle
+                                .get_pool_priofee(&pool, &pool_writable_accounts)
+                                .await
+                                .per_transaction
+                                .medium,
                         );
                         std::cmp::min(priofee, MAX_AUTO_PRIORITY_FEE_LAMPORTS)
                     }
@@ -385,46 +1030,163 @@ impl GfxSwapClient {
                     priofee,
                     compute_units
                 );
-                let compute_ix =
-                    solana_sdk::compute_budget::ComputeBudgetInstruction::set_compute_unit_price(
-                        cu_price,
-                    );
-                instructions.compute_budget_instructions.push(compute_ix);
+                Prioritization::CuPrice(cu_price)
             }
-            (None, Some(PrioritizationFeeLamports::AutoMultiplier(multiplier))) => {
-                let priofee = (*multiplier as u64)
-                    .checked_mul(100_000)
-                    .ok_or(SwapError::PrioritizationFeeOverflow)?;
+            (None, Some(PrioritizationFeeLamports::AutoMultiplier(multiplier)), _) => {
+                // Multiplies the live estimate. Without one, the minimum fee is multiplied.
+                let estimate = match &self.priofees_handle {
+                    Some(handle) => std::cmp::max(
+                        MIN_PRIORITY_FEE,
+                        handle
+                            .get_pool_priofee(&pool, &pool_writable_accounts)
+                            .await
+                            .per_transaction
+                            .medium,
+                    ),
+                    None => MIN_PRIORITY_FEE,
+                };
+                let priofee = estimate
+                    .checked_mul(u64::from(*multiplier))
+                    .ok_or(SwapError::PrioritizationFeeOverflow)?
+                    .min(MAX_AUTO_PRIORITY_FEE_LAMPORTS);
                 let cu_price = calculate_cu_price(priofee, compute_units);
                 log::trace!(
-                    "prioritization-fee-lamports: cu-price={}, multiplier={}. priofee={}, cu-limit={}",
+                    "prioritization-fee-lamports: cu-price={}, multiplier={}, estimate={}. priofee={}, cu-limit={}",
                     cu_price,
                     multiplier,
+                    estimate,
                     priofee,
                     compute_units
                 );
+                Prioritization::CuPrice(cu_price)
+            }
+            (None, Some(PrioritizationFeeLamports::JitoTipLamports(jito_tip)), _) => {
+                Prioritization::JitoTip(*jito_tip)
+            }
+            (None, None, Some(ExtendedPrioritization::AutoJitoTip)) => {
+                let jito_tip = self
+                    .jito
+                    .auto_tip_lamports()
+                    .await
+                    .ok_or_else(|| SwapError::JitoUnavailable("no tip floor yet".to_string()))?
+                    .min(MAX_AUTO_PRIORITY_FEE_LAMPORTS);
+                log::trace!("auto jito-tip: {}", jito_tip);
+                Prioritization::JitoTip(jito_tip)
+            }
+        };
+        // Without a measured limit, `compute_units` is what the runtime gives the transaction
+        // anyway, so it's only requested for sponsored swaps: the sponsor's cap is checked
+        // against that figure, and the request keeps it from changing under the sponsor.
+        if dynamic_compute.is_some() || resolved.sponsored {
+            instructions.compute_budget_instructions.push(
+                solana_sdk::compute_budget::ComputeBudgetInstruction::set_compute_unit_limit(
+                    compute_units,
+                ),
+            );
+        }
+        let prioritization_fee_lamports = match prioritization {
+            Prioritization::CuPrice(cu_price) => {
                 let compute_ix =
                     solana_sdk::compute_budget::ComputeBudgetInstruction::set_compute_unit_price(
                         cu_price,
                     );
                 instructions.compute_budget_instructions.push(compute_ix);
+                calculate_priority_fee(cu_price, compute_units)
             }
-            (None, Some(PrioritizationFeeLamports::JitoTipLamports(jito_tip))) => {
-                let tip_ix = build_jito_tip_ix(&req.user_public_key, *jito_tip);
-                instructions.setup_instructions.push(tip_ix);
+            Prioritization::JitoTip(jito_tip) => {
+                if jito_tip < MIN_TIP_LAMPORTS {
+                    return Err(SwapError::InvalidRequest(format!(
+                        "jitoTipLamports must be at least {}",
+                        MIN_TIP_LAMPORTS
+                    )));
+                }
+                let tip_account =
+                    self.jito.random_tip_account().await.ok_or_else(|| {
+                        SwapError::JitoUnavailable("no tip accounts yet".to_string())
+                    })?;
+                instructions
+                    .setup_instructions
+                    .push(solana_sdk::system_instruction::transfer(
+                        &resolved.fee_payer,
+                        &tip_account,
+                        jito_tip,
+                    ));
+                jito_tip
+            }
+        };
+
+        if resolved.preflight_checks {
+            // The user's SOL covers the wrapped amount, and the fees and rent when paying them
+            let mut required = if wraps_sol {
+                req.quote_response.in_amount
+            } else {
+                0
+            };
+            if resolved.fee_payer == req.user_public_key {
+                let signatures =
+                    1 + u64::from(resolved.durable_nonce.is_some_and(|durable_nonce| {
+                        durable_nonce.authority != req.user_public_key
+                    }));
+                required = required
+                    .saturating_add(LAMPORTS_PER_SIGNATURE * signatures)
+                    .saturating_add(prioritization_fee_lamports)
+                    .saturating_add(ata_rent_lamports);
+            }
+            let available = user_account.map_or(0, |account| account.lamports);
+            if required > available {
+                return Err(SwapError::InsufficientBalance {
+                    mint: spl_token::native_mint::ID,
+                    required,
+                    available,
+                });
             }
         }
 
-        Ok(instructions)
+        Ok(SwapInstructions {
+            instructions,
+            prioritization_fee_lamports,
+            compute_unit_limit: compute_units,
+            ata_rent_lamports,
+        })
+    }
+}
+
+/// The current value of an initialized nonce account held by `durable_nonce.authority`
+fn nonce_blockhash(durable_nonce: &DurableNonce, account: &Account) -> Result<Hash, SwapError> {
+    if account.owner != solana_sdk::system_program::id() {
+        return Err(SwapError::InvalidNonceAccount(format!(
+            "{} is not a system account",
+            durable_nonce.account
+        )));
+    }
+    let not_a_nonce =
+        || SwapError::InvalidNonceAccount(format!("{} is not a nonce", durable_nonce.account));
+    match bincode::deserialize::<NonceVersions>(&account.data).map_err(|_| not_a_nonce())? {
+        NonceVersions::Current(state) => match *state {
+            NonceState::Initialized(data) if data.authority == durable_nonce.authority => {
+                Ok(data.blockhash())
+            }
+            NonceState::Initialized(data) => Err(SwapError::InvalidNonceAccount(format!(
+                "nonce authority is {}, not {}",
+                data.authority, durable_nonce.authority
+            ))),
+            NonceState::Uninitialized => Err(not_a_nonce()),
+        },
+        NonceVersions::Legacy(_) => Err(SwapError::InvalidNonceAccount(format!(
+            "{} is a legacy nonce. Advance it once to upgrade",
+            durable_nonce.account
+        ))),
     }
 }
 
+/// `advance_nonce` goes first, as the runtime requires of durable-nonce transactions
 fn build_transaction(
     instructions: SwapInstructionsResponse,
     payer: Option<&Pubkey>,
     blockhash: Option<Hash>,
+    advance_nonce: Option<Instruction>,
 ) -> VersionedTransaction {
-    let mut final_instructions = Vec::new();
+    let mut final_instructions = Vec::from_iter(advance_nonce);
     let SwapInstructionsResponse {
         token_ledger_instruction: _,
         compute_budget_instructions,
@@ -444,11 +1206,127 @@ fn build_transaction(
         message.set_recent_blockhash(hash);
     }
     VersionedTransaction {
-        signatures: vec![Signature::default()],
+        // A nonce authority other than the payer signs too
+        signatures: vec![
+            Signature::default();
+            usize::from(message.header().num_required_signatures)
+        ],
         message,
     }
 }
 
+/// The swap's remaining accounts, which Gamma reads by position: four referral slots, then the
+/// input mint's transfer-hook accounts, then the output mint's. Without a referral the slots are
+/// left out, unless hook accounts follow, in which case they're filled with the program ID,
+/// anchor's placeholder for an absent optional account.
+///
+/// The layout is the program's, not this API's: it's set by how Gamma's `swap_base_input` and
+/// `swap_base_output` handlers split `ctx.remaining_accounts`, in the `gamma` dependency
+/// (<https://github.com/GooseFX1/gamma-swap>, at the revision `Cargo.lock` pins). Check this
+/// against those handlers when bumping it.
+fn swap_remaining_accounts(
+    referral: Option<(Pubkey, Pubkey)>,
+    input_hook_accounts: Vec<AccountMeta>,
+    output_hook_accounts: Vec<AccountMeta>,
+) -> Vec<AccountMeta> {
+    let has_hooks = !input_hook_accounts.is_empty() || !output_hook_accounts.is_empty();
+    let mut accounts = Vec::new();
+    match referral {
+        Some((referral_account, referral_token_account)) => accounts.extend([
+            AccountMeta::new_readonly(gamma::ID, false),
+            AccountMeta::new_readonly(gamma::ID, false),
+            AccountMeta::new_readonly(referral_account, false),
+            AccountMeta::new(referral_token_account, false),
+        ]),
+        None if has_hooks => {
+            accounts.extend(std::iter::repeat(AccountMeta::new_readonly(gamma::ID, false)).take(4))
+        }
+        None => {}
+    }
+    accounts.extend(input_hook_accounts);
+    accounts.extend(output_hook_accounts);
+    accounts
+}
+
+/// Whether a token-2022 account has the MemoTransfer extension enabled. Accounts that don't
+/// exist yet are created without it.
+fn requires_incoming_memo(token_account: Option<Account>) -> bool {
+    token_account.is_some_and(|mut account| {
+        StateWithExtensionsMut::<spl_token_2022::state::Account>::unpack(&mut account.data)
+            .is_ok_and(|state| memo_transfer::memo_required(&state))
+    })
+}
+
+/// The input token account exists, isn't frozen, and holds what the swap can spend
+fn check_input_token_account(
+    token_account: &Pubkey,
+    account: Option<&Account>,
+    mint: &Pubkey,
+    required: u64,
+) -> Result<(), SwapError> {
+    let available = match account {
+        Some(account) => {
+            let state =
+                StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+                    .map_err(|_| {
+                        SwapError::InvalidRequest(format!(
+                            "{} is not a token account",
+                            token_account
+                        ))
+                    })?;
+            if state.base.is_frozen() {
+                return Err(SwapError::InputAccountFrozen(*token_account));
+            }
+            state.base.amount
+        }
+        None => 0,
+    };
+    if required > available {
+        return Err(SwapError::InsufficientBalance {
+            mint: *mint,
+            required,
+            available,
+        });
+    }
+    Ok(())
+}
+
+/// A given destination exists, belongs to the output token program and mint, and isn't frozen
+fn check_destination_token_account(
+    token_account: &Pubkey,
+    account: Option<&Account>,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Result<(), SwapError> {
+    let invalid =
+        |reason: String| SwapError::InvalidDestinationAccount(format!("{token_account} {reason}"));
+    let account = account.ok_or_else(|| invalid("doesn't exist".to_string()))?;
+    if account.owner != *token_program {
+        return Err(invalid(format!(
+            "is owned by {}, not {}",
+            account.owner, token_program
+        )));
+    }
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+        .map_err(|_| invalid("is not a token account".to_string()))?;
+    if state.base.mint != *mint {
+        return Err(invalid(format!("holds {}, not {}", state.base.mint, mint)));
+    }
+    if state.base.is_frozen() {
+        return Err(invalid("is frozen".to_string()));
+    }
+    Ok(())
+}
+
+/// The compute-unit limit the runtime gives a transaction that doesn't request one: 200k for each
+/// instruction other than compute-budget ones, up to 1.4M
+fn default_compute_unit_limit(instruction_count: usize) -> u32 {
+    u32::try_from(instruction_count)
+        .unwrap_or(u32::MAX)
+        .saturating_mul(DEFAULT_INSTRUCTION_COMPUTE_UNIT)
+        .min(MAX_COMPUTE_UNIT_LIMIT)
+}
+
 fn calculate_cu_price(priority_fee: u64, compute_units: u32) -> u64 {
     let cu_price = (priority_fee as u128)
         .checked_mul(MICRO_LAMPORTS_PER_LAMPORT as u128)
@@ -460,19 +1338,137 @@ fn calculate_cu_price(priority_fee: u64, compute_units: u32) -> u64 {
     u64::try_from(cu_price).unwrap_or(u64::MAX)
 }
 
-const JITO_TIP_ACCOUNTS: [Pubkey; 8] = [
-    pubkey!("96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5"),
-    pubkey!("HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe"),
-    pubkey!("Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY"),
-    pubkey!("ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49"),
-    pubkey!("DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh"),
-    pubkey!("ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt"),
-    pubkey!("DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL"),
-    pubkey!("3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT"),
-];
-
-fn build_jito_tip_ix(from: &Pubkey, tip: u64) -> Instruction {
-    let random_recipient =
-        &JITO_TIP_ACCOUNTS[rand::thread_rng().gen_range(0..JITO_TIP_ACCOUNTS.len())];
-    solana_sdk::system_instruction::transfer(from, random_recipient, tip)
+/// What a transaction pays at `cu_price` micro-lamports, rounded up like agave
+pub fn calculate_priority_fee(cu_price: u64, compute_units: u32) -> u64 {
+    let priority_fee = (cu_price as u128)
+        .saturating_mul(compute_units as u128)
+        .saturating_add(MICRO_LAMPORTS_PER_LAMPORT as u128 - 1)
+        / MICRO_LAMPORTS_PER_LAMPORT as u128;
+    u64::try_from(priority_fee).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_limit_is_per_instruction_up_to_the_max() {
+        assert_eq!(default_compute_unit_limit(1), 200_000);
+        // Creating the output ATA, the swap, and unwrapping SOL
+        assert_eq!(default_compute_unit_limit(3), 600_000);
+        assert_eq!(default_compute_unit_limit(7), 1_400_000);
+        assert_eq!(default_compute_unit_limit(8), 1_400_000);
+        assert_eq!(default_compute_unit_limit(usize::MAX), 1_400_000);
+    }
+
+    fn nonce_account(owner: Pubkey, state: NonceState) -> Account {
+        Account {
+            lamports: 1_447_680,
+            data: bincode::serialize(&NonceVersions::new(state)).unwrap(),
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    fn initialized(authority: Pubkey, blockhash: &Hash) -> NonceState {
+        NonceState::Initialized(solana_sdk::nonce::state::Data::new(
+            authority,
+            solana_sdk::nonce::state::DurableNonce::from_blockhash(blockhash),
+            5_000,
+        ))
+    }
+
+    #[test]
+    fn nonce_value_is_the_durable_nonce() {
+        let durable_nonce = DurableNonce {
+            account: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+        };
+        let blockhash = Hash::new_unique();
+        let account = nonce_account(
+            solana_sdk::system_program::id(),
+            initialized(durable_nonce.authority, &blockhash),
+        );
+        assert_eq!(
+            nonce_blockhash(&durable_nonce, &account).unwrap(),
+            solana_sdk::nonce::state::DurableNonce::from_blockhash(&blockhash)
+                .as_hash()
+                .to_owned()
+        );
+    }
+
+    #[test]
+    fn rejects_accounts_that_are_not_usable_nonces() {
+        let durable_nonce = DurableNonce {
+            account: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+        };
+        let rejected = |account: Account| {
+            matches!(
+                nonce_blockhash(&durable_nonce, &account),
+                Err(SwapError::InvalidNonceAccount(_))
+            )
+        };
+        let nonce = initialized(durable_nonce.authority, &Hash::new_unique());
+        // Owned by another program
+        assert!(rejected(nonce_account(Pubkey::new_unique(), nonce.clone())));
+        // Uninitialized
+        assert!(rejected(nonce_account(
+            solana_sdk::system_program::id(),
+            NonceState::Uninitialized
+        )));
+        // Held by another authority
+        assert!(rejected(nonce_account(
+            solana_sdk::system_program::id(),
+            initialized(Pubkey::new_unique(), &Hash::new_unique())
+        )));
+        // A plain wallet
+        assert!(rejected(Account::new(
+            1_000_000,
+            0,
+            &solana_sdk::system_program::id()
+        )));
+    }
+
+    fn hook_account(seed: u8) -> AccountMeta {
+        AccountMeta::new_readonly(Pubkey::new_from_array([seed; 32]), false)
+    }
+
+    #[test]
+    fn remaining_accounts_without_referral_or_hooks_are_empty() {
+        assert!(swap_remaining_accounts(None, vec![], vec![]).is_empty());
+    }
+
+    #[test]
+    fn referral_accounts_take_the_first_four_slots() {
+        let referral = Pubkey::new_unique();
+        let referral_token_account = Pubkey::new_unique();
+        let accounts = swap_remaining_accounts(
+            Some((referral, referral_token_account)),
+            vec![hook_account(1)],
+            vec![hook_account(2)],
+        );
+        assert_eq!(
+            accounts,
+            vec![
+                AccountMeta::new_readonly(gamma::ID, false),
+                AccountMeta::new_readonly(gamma::ID, false),
+                AccountMeta::new_readonly(referral, false),
+                AccountMeta::new(referral_token_account, false),
+                hook_account(1),
+                hook_account(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn hook_accounts_without_referral_keep_their_offset() {
+        let accounts = swap_remaining_accounts(None, vec![], vec![hook_account(2)]);
+        assert_eq!(accounts.len(), 5);
+        assert!(accounts[..4]
+            .iter()
+            .all(|account| account.pubkey == gamma::ID && !account.is_writable));
+        assert_eq!(accounts[4], hook_account(2));
+    }
 }
@@ -0,0 +1,415 @@
+use serde::Serialize;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use spl_token_2022::extension::AccountType;
+use spl_token_2022::state::Account;
+use thiserror::Error;
+
+/// Offset of the TLV data in a token-2022 mint, after the base mint padded to `Account::LEN` and
+/// the account-type byte
+const MINT_TLV_START: usize = Account::LEN + 1;
+/// `AccountState::Frozen` as stored in the `DefaultAccountState` extension
+const ACCOUNT_STATE_FROZEN: u8 = 2;
+
+/// Mint extensions as numbered by the token-2022 program. This includes types newer than the
+/// `spl-token-2022` version we build against so they can still be recognized in mint data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum TokenExtension {
+    TransferFeeConfig,
+    MintCloseAuthority,
+    ConfidentialTransferMint,
+    DefaultAccountState,
+    NonTransferable,
+    InterestBearingConfig,
+    PermanentDelegate,
+    TransferHook,
+    ConfidentialTransferFeeConfig,
+    MetadataPointer,
+    TokenMetadata,
+    GroupPointer,
+    TokenGroup,
+    GroupMemberPointer,
+    TokenGroupMember,
+    ConfidentialMintBurn,
+    ScaledUiAmount,
+    Pausable,
+}
+
+impl TokenExtension {
+    /// The default set of extensions a deployment accepts
+    pub const DEFAULT_ALLOWED: [TokenExtension; 17] = [
+        TokenExtension::TransferFeeConfig,
+        TokenExtension::MintCloseAuthority,
+        TokenExtension::ConfidentialTransferMint,
+        TokenExtension::DefaultAccountState,
+        TokenExtension::InterestBearingConfig,
+        TokenExtension::PermanentDelegate,
+        TokenExtension::TransferHook,
+        TokenExtension::ConfidentialTransferFeeConfig,
+        TokenExtension::MetadataPointer,
+        TokenExtension::TokenMetadata,
+        TokenExtension::GroupPointer,
+        TokenExtension::TokenGroup,
+        TokenExtension::GroupMemberPointer,
+        TokenExtension::TokenGroupMember,
+        TokenExtension::ConfidentialMintBurn,
+        TokenExtension::ScaledUiAmount,
+        TokenExtension::Pausable,
+    ];
+
+    /// Maps a mint extension's TLV type. Account-only and padding types aren't mint extensions
+    fn from_tlv_type(extension_type: u16) -> Option<Self> {
+        Some(match extension_type {
+            1 => TokenExtension::TransferFeeConfig,
+            3 => TokenExtension::MintCloseAuthority,
+            4 => TokenExtension::ConfidentialTransferMint,
+            6 => TokenExtension::DefaultAccountState,
+            9 => TokenExtension::NonTransferable,
+            10 => TokenExtension::InterestBearingConfig,
+            12 => TokenExtension::PermanentDelegate,
+            14 => TokenExtension::TransferHook,
+            16 => TokenExtension::ConfidentialTransferFeeConfig,
+            18 => TokenExtension::MetadataPointer,
+            19 => TokenExtension::TokenMetadata,
+            20 => TokenExtension::GroupPointer,
+            21 => TokenExtension::TokenGroup,
+            22 => TokenExtension::GroupMemberPointer,
+            23 => TokenExtension::TokenGroupMember,
+            24 => TokenExtension::ConfidentialMintBurn,
+            25 => TokenExtension::ScaledUiAmount,
+            26 => TokenExtension::Pausable,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TokenPolicyError {
+    #[error("Mint {0} is non-transferable")]
+    NonTransferable(Pubkey),
+    #[error("Mint {0} is paused")]
+    Paused(Pubkey),
+    #[error("Mint {0} freezes new token accounts by default")]
+    FrozenByDefault(Pubkey),
+    #[error("Mint {mint} uses the {extension:?} extension, which is not supported")]
+    ExtensionNotAllowed {
+        mint: Pubkey,
+        extension: TokenExtension,
+    },
+    #[error("Mint {mint} uses an unrecognized extension(type={extension_type})")]
+    UnknownExtension { mint: Pubkey, extension_type: u16 },
+    #[error("Mint {0} has malformed extension data")]
+    InvalidMintData(Pubkey),
+}
+
+/// Decides which token-2022 mints can be quoted and swapped, and what users should be warned about
+#[derive(Debug, Clone)]
+pub struct TokenExtensionPolicy {
+    allowed: Vec<TokenExtension>,
+}
+
+impl Default for TokenExtensionPolicy {
+    fn default() -> Self {
+        TokenExtensionPolicy {
+            allowed: TokenExtension::DEFAULT_ALLOWED.to_vec(),
+        }
+    }
+}
+
+impl TokenExtensionPolicy {
+    pub fn new(allowed: Vec<TokenExtension>) -> Self {
+        TokenExtensionPolicy { allowed }
+    }
+
+    /// Checks a mint against the policy. Returns warnings for extensions that are tradeable but
+    /// that users should know about.
+    pub fn check_mint(&self, mint: &Pubkey, data: &[u8]) -> Result<Vec<String>, TokenPolicyError> {
+        let mut warnings = vec![];
+        for (extension_type, value) in mint_extensions(mint, data)? {
+            let Some(extension) = TokenExtension::from_tlv_type(extension_type) else {
+                return Err(TokenPolicyError::UnknownExtension {
+                    mint: *mint,
+                    extension_type,
+                });
+            };
+            // Non-transferable mints can never be swapped, regardless of configuration
+            if extension == TokenExtension::NonTransferable {
+                return Err(TokenPolicyError::NonTransferable(*mint));
+            }
+            if !self.allowed.contains(&extension) {
+                return Err(TokenPolicyError::ExtensionNotAllowed {
+                    mint: *mint,
+                    extension,
+                });
+            }
+
+            match extension {
+                TokenExtension::PermanentDelegate => {
+                    warnings.push(format!("Mint {}: permanent delegate present", mint));
+                }
+                // Only an issue when a new account has to be created, see `frozen_by_default`
+                TokenExtension::DefaultAccountState
+                    if value.first() == Some(&ACCOUNT_STATE_FROZEN) =>
+                {
+                    warnings.push(format!(
+                        "Mint {}: new token accounts are frozen by default and must be thawed by the freeze authority",
+                        mint
+                    ));
+                }
+                TokenExtension::InterestBearingConfig => {
+                    // rate_authority(32) + initialization_timestamp(8) + pre_update_average_rate(2)
+                    // + last_update_timestamp(8) + current_rate(2)
+                    let current_rate = value
+                        .get(50..52)
+                        .map(|rate| i16::from_le_bytes([rate[0], rate[1]]))
+                        .ok_or(TokenPolicyError::InvalidMintData(*mint))?;
+                    warnings.push(format!(
+                        "Mint {}: interest-bearing at {} bps, UI amounts differ from raw amounts",
+                        mint, current_rate
+                    ));
+                }
+                TokenExtension::ScaledUiAmount => {
+                    warnings.push(format!(
+                        "Mint {}: UI amounts are scaled and differ from raw amounts",
+                        mint
+                    ));
+                }
+                TokenExtension::Pausable => {
+                    // authority(32) + paused(1)
+                    match value.get(32) {
+                        Some(0) => warnings.push(format!("Mint {}: mint can be paused", mint)),
+                        Some(_) => return Err(TokenPolicyError::Paused(*mint)),
+                        None => return Err(TokenPolicyError::InvalidMintData(*mint)),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(warnings)
+    }
+}
+
+/// Walks the TLV entries of a mint. Legacy spl-token mints have none.
+fn mint_extensions<'a>(
+    mint: &Pubkey,
+    data: &'a [u8],
+) -> Result<Vec<(u16, &'a [u8])>, TokenPolicyError> {
+    if data.len() <= spl_token_2022::state::Mint::LEN {
+        return Ok(vec![]);
+    }
+    if data.get(Account::LEN) != Some(&(AccountType::Mint as u8)) {
+        return Err(TokenPolicyError::InvalidMintData(*mint));
+    }
+
+    let mut extensions = vec![];
+    let mut tlv = &data[MINT_TLV_START..];
+    while tlv.len() >= 4 {
+        let extension_type = u16::from_le_bytes([tlv[0], tlv[1]]);
+        let length = usize::from(u16::from_le_bytes([tlv[2], tlv[3]]));
+        // Uninitialized entries are padding, and nothing comes after them
+        if extension_type == 0 {
+            break;
+        }
+        let value = tlv
+            .get(4..4 + length)
+            .ok_or(TokenPolicyError::InvalidMintData(*mint))?;
+        extensions.push((extension_type, value));
+        tlv = &tlv[4 + length..];
+    }
+
+    Ok(extensions)
+}
+
+/// Whether new token accounts for a mint start frozen. An account created in the same
+/// transaction as a transfer into it couldn't receive, so callers reject that case.
+pub fn frozen_by_default(mint: &Pubkey, data: &[u8]) -> Result<bool, TokenPolicyError> {
+    Ok(mint_extensions(mint, data)?
+        .into_iter()
+        .any(|(extension_type, value)| {
+            TokenExtension::from_tlv_type(extension_type)
+                == Some(TokenExtension::DefaultAccountState)
+                && value.first() == Some(&ACCOUNT_STATE_FROZEN)
+        }))
+}
+
+/// Size of a new associated token account for a mint. Token-2022 ATAs carry `ImmutableOwner`,
+/// plus the account extensions the mint's extensions require at initialization.
+pub fn associated_token_account_len(
+    mint: &Pubkey,
+    mint_data: &[u8],
+    token_program: &Pubkey,
+) -> Result<usize, TokenPolicyError> {
+    if *token_program != spl_token_2022::ID {
+        return Ok(Account::LEN);
+    }
+
+    // The account-type byte, then a 4 byte TLV header per extension plus its value
+    let mut len = Account::LEN + 1 + 4; // ImmutableOwner has no value
+    for (extension_type, _) in mint_extensions(mint, mint_data)? {
+        len += match TokenExtension::from_tlv_type(extension_type) {
+            // TransferFeeAmount holds the withheld amount
+            Some(TokenExtension::TransferFeeConfig) => 4 + 8,
+            // TransferHookAccount holds the transferring flag
+            Some(TokenExtension::TransferHook) => 4 + 1,
+            // NonTransferableAccount and PausableAccount are markers
+            Some(TokenExtension::NonTransferable | TokenExtension::Pausable) => 4,
+            _ => 0,
+        };
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A token-2022 mint carrying the given TLV entries
+    fn mint_data(extensions: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut data = vec![0; Account::LEN];
+        data.push(AccountType::Mint as u8);
+        for (extension_type, value) in extensions {
+            data.extend(extension_type.to_le_bytes());
+            data.extend((value.len() as u16).to_le_bytes());
+            data.extend(*value);
+        }
+        data
+    }
+
+    #[test]
+    fn legacy_mints_have_no_extensions() {
+        let mint = Pubkey::new_unique();
+        let data = vec![0; spl_token_2022::state::Mint::LEN];
+        assert!(mint_extensions(&mint, &data).unwrap().is_empty());
+        assert!(TokenExtensionPolicy::default()
+            .check_mint(&mint, &data)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn walks_entries_until_padding() {
+        let mint = Pubkey::new_unique();
+        let mut data = mint_data(&[(18, &[7; 64]), (3, &[9; 32])]);
+        data.extend([0; 8]);
+        let extensions = mint_extensions(&mint, &data).unwrap();
+        assert_eq!(extensions, vec![(18, &[7; 64][..]), (3, &[9; 32][..])]);
+    }
+
+    #[test]
+    fn rejects_truncated_entries() {
+        let mint = Pubkey::new_unique();
+        let mut data = mint_data(&[(18, &[7; 64])]);
+        data.truncate(data.len() - 1);
+        assert!(matches!(
+            mint_extensions(&mint, &data),
+            Err(TokenPolicyError::InvalidMintData(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_data_that_isnt_a_mint() {
+        let mint = Pubkey::new_unique();
+        let mut data = mint_data(&[]);
+        data[Account::LEN] = AccountType::Account as u8;
+        assert!(matches!(
+            mint_extensions(&mint, &data),
+            Err(TokenPolicyError::InvalidMintData(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_untradeable_mints() {
+        let policy = TokenExtensionPolicy::default();
+        let mint = Pubkey::new_unique();
+        let check = |extensions: &[(u16, &[u8])]| policy.check_mint(&mint, &mint_data(extensions));
+
+        assert!(matches!(
+            check(&[(9, &[])]),
+            Err(TokenPolicyError::NonTransferable(_))
+        ));
+        let mut paused = [0; 33];
+        paused[32] = 1;
+        assert!(matches!(
+            check(&[(26, &paused)]),
+            Err(TokenPolicyError::Paused(_))
+        ));
+        assert!(matches!(
+            check(&[(200, &[])]),
+            Err(TokenPolicyError::UnknownExtension {
+                extension_type: 200,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_extensions_outside_the_policy() {
+        let policy = TokenExtensionPolicy::new(vec![TokenExtension::MetadataPointer]);
+        let mint = Pubkey::new_unique();
+        assert!(policy
+            .check_mint(&mint, &mint_data(&[(18, &[0; 64])]))
+            .is_ok());
+        assert!(matches!(
+            policy.check_mint(&mint, &mint_data(&[(1, &[0; 108])])),
+            Err(TokenPolicyError::ExtensionNotAllowed {
+                extension: TokenExtension::TransferFeeConfig,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn warns_about_tradeable_extensions() {
+        let policy = TokenExtensionPolicy::default();
+        let mint = Pubkey::new_unique();
+        let mut interest_bearing = [0; 52];
+        interest_bearing[50..52].copy_from_slice(&500i16.to_le_bytes());
+        let warnings = policy
+            .check_mint(
+                &mint,
+                &mint_data(&[
+                    (6, &[1]),
+                    (10, &interest_bearing),
+                    (12, &[0; 32]),
+                    (26, &[0; 33]),
+                ]),
+            )
+            .unwrap();
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].contains("500 bps"));
+        assert!(warnings[1].contains("permanent delegate"));
+        assert!(warnings[2].contains("can be paused"));
+    }
+
+    #[test]
+    fn frozen_by_default_mints_are_tradeable_with_a_warning() {
+        let mint = Pubkey::new_unique();
+        let frozen = mint_data(&[(6, &[ACCOUNT_STATE_FROZEN])]);
+        let warnings = TokenExtensionPolicy::default()
+            .check_mint(&mint, &frozen)
+            .unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("frozen by default"));
+
+        assert!(frozen_by_default(&mint, &frozen).unwrap());
+        assert!(!frozen_by_default(&mint, &mint_data(&[(6, &[1])])).unwrap());
+        assert!(!frozen_by_default(&mint, &[0; spl_token_2022::state::Mint::LEN]).unwrap());
+    }
+
+    #[test]
+    fn sizes_associated_token_accounts() {
+        let mint = Pubkey::new_unique();
+        let data = mint_data(&[(1, &[0; 108]), (14, &[0; 64]), (18, &[0; 64])]);
+        assert_eq!(
+            associated_token_account_len(&mint, &data, &spl_token::ID).unwrap(),
+            Account::LEN
+        );
+        // Account type, ImmutableOwner, TransferFeeAmount and TransferHookAccount
+        assert_eq!(
+            associated_token_account_len(&mint, &data, &spl_token_2022::ID).unwrap(),
+            Account::LEN + 1 + 4 + (4 + 8) + (4 + 1)
+        );
+    }
+}
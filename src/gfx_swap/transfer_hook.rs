@@ -0,0 +1,103 @@
+use super::swap::SwapError;
+use super::GfxSwapClient;
+use crate::accounts::{AccountsError, AccountsGetter};
+
+use anchor_lang::prelude::AccountMeta;
+use serde::{Deserialize, Serialize};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use spl_token_2022::extension::{transfer_hook, StateWithExtensions};
+use spl_token_2022::offchain::{resolve_extra_transfer_account_metas, AccountDataResult};
+use spl_token_2022::state::Mint;
+use swap_api::serde_helpers::field_as_string;
+
+/// A mint in a quote that requires a CPI into a transfer-hook program on every transfer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferHookMint {
+    #[serde(with = "field_as_string")]
+    pub mint: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub program_id: Pubkey,
+}
+
+/// Returns the transfer-hook program for a mint, if the TransferHook extension is set
+pub fn get_transfer_hook_program_id(mint_data: &[u8]) -> Option<Pubkey> {
+    let mint = StateWithExtensions::<Mint>::unpack(mint_data).ok()?;
+    transfer_hook::get_program_id(&mint)
+}
+
+/// The address of the `ExtraAccountMetaList` account for a mint with a transfer-hook, if any
+pub fn get_extra_account_metas_address(mint: &Pubkey, mint_data: &[u8]) -> Option<Pubkey> {
+    get_transfer_hook_program_id(mint_data).map(|program_id| {
+        spl_transfer_hook_interface::get_extra_account_metas_address(mint, &program_id)
+    })
+}
+
+/// One leg of the swap, i.e a single `transfer_checked` done by the Gamma program
+pub struct TransferLeg {
+    pub source: Pubkey,
+    pub mint: Pubkey,
+    pub destination: Pubkey,
+    pub authority: Pubkey,
+    pub token_program: Pubkey,
+    pub amount: u64,
+}
+
+impl GfxSwapClient {
+    /// Resolves the extra accounts the mint's transfer-hook program requires for this leg. Returns
+    /// an empty list if the mint has no transfer-hook.
+    pub async fn resolve_transfer_hook_accounts(
+        &self,
+        leg: TransferLeg,
+    ) -> Result<Vec<AccountMeta>, SwapError> {
+        if leg.token_program != spl_token_2022::ID {
+            return Ok(vec![]);
+        }
+
+        let mint_data = self.accounts_service.get_account(&leg.mint).await?;
+        let mint = StateWithExtensions::<Mint>::unpack(&mint_data)
+            .map_err(|e| SwapError::TransferHook(e.to_string()))?;
+        if transfer_hook::get_program_id(&mint).is_none() {
+            return Ok(vec![]);
+        }
+
+        let mut transfer_ix = spl_token_2022::instruction::transfer_checked(
+            &leg.token_program,
+            &leg.source,
+            &leg.mint,
+            &leg.destination,
+            &leg.authority,
+            &[],
+            leg.amount,
+            mint.base.decimals,
+        )
+        .map_err(|e| SwapError::TransferHook(e.to_string()))?;
+        let base_accounts_len = transfer_ix.accounts.len();
+
+        resolve_extra_transfer_account_metas(
+            &mut transfer_ix,
+            |address| self.fetch_hook_account_data(address),
+            &leg.mint,
+        )
+        .await
+        .map_err(|e| SwapError::TransferHook(e.to_string()))?;
+
+        Ok(transfer_ix.accounts.split_off(base_accounts_len))
+    }
+
+    /// Hook validation accounts are tracked by the accounts store. Anything else the hook depends
+    /// on(e.g user token accounts) is fetched from RPC.
+    async fn fetch_hook_account_data(&self, address: Pubkey) -> AccountDataResult {
+        match self.accounts_service.get_account(&address).await {
+            Ok(data) => Ok(Some(data)),
+            Err(AccountsError::NotFound) => Ok(self
+                .solana_rpc
+                .get_account_with_commitment(&address, CommitmentConfig::confirmed())
+                .await?
+                .value
+                .map(|account| account.data)),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use super::GfxSwapClient;
+use crate::accounts::{AccountsError, AccountsGetter};
+
+use anchor_lang::AccountDeserialize;
+use gamma::states::{ObservationState, PoolState};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use rust_decimal::MathematicalOps;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use swap_api::serde_helpers::field_as_string;
+use thiserror::Error;
+
+/// Window used when the request doesn't specify one, in seconds
+const DEFAULT_TWAP_WINDOW_SECS: u64 = 300;
+/// Cumulative prices in observations are Q32.32 fixed-point
+const Q32: u128 = 1 << 32;
+
+#[derive(Debug, Error)]
+pub enum TwapError {
+    #[error("Error fetching account: {0}")]
+    Accounts(#[from] AccountsError),
+    #[error("Error deserializing anchor account: {0}")]
+    Anchor(#[from] anchor_lang::error::Error),
+    #[error("RPC error: {0}")]
+    ClientError(#[from] solana_rpc_client_api::client_error::Error),
+    #[error("{0}")]
+    InvalidRequest(String),
+    #[error("Pool {0} is not tracked")]
+    PoolNotFound(Pubkey),
+    #[error("Pool has fewer than two observations in the requested window")]
+    NotEnoughObservations,
+    #[error("Average price is out of range")]
+    Overflow,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TwapRequest {
+    /// How far back to average, in seconds
+    pub window: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolTwap {
+    #[serde(with = "field_as_string")]
+    pub pool: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub token_0_mint: Pubkey,
+    #[serde(with = "field_as_string")]
+    pub token_1_mint: Pubkey,
+    pub requested_window: u64,
+    /// Seconds between the oldest and newest observation used. Usually a bit longer than requested
+    /// since observations are spaced out, and shorter if the pool is younger than the window or
+    /// the ring buffer doesn't reach back far enough.
+    pub covered_window: u64,
+    /// Number of observations in the covered window
+    pub observations: usize,
+    /// Seconds since the newest observation, by the cluster clock
+    pub newest_observation_age: u64,
+    /// Time-weighted price of one whole token0 in token1
+    pub token_0_twap: String,
+    /// Time-weighted price of one whole token1 in token0
+    pub token_1_twap: String,
+    /// Current price of one whole token0 in token1, from pool reserves
+    pub token_0_spot: String,
+    /// How far the spot price is from the TWAP, in percent
+    pub deviation_from_spot_pct: String,
+}
+
+impl GfxSwapClient {
+    /// Computes time-weighted average prices from a pool's observation ring buffer
+    pub async fn twap(&self, pool: &Pubkey, req: &TwapRequest) -> Result<PoolTwap, TwapError> {
+        let window = req.window.unwrap_or(DEFAULT_TWAP_WINDOW_SECS);
+        if window == 0 {
+            return Err(TwapError::InvalidRequest(
+                "window must be greater than zero".to_string(),
+            ));
+        }
+
+        // Other accounts in the store, like vaults and mints, aren't pools either
+        if !self.accounts_service.is_pool(pool) {
+            return Err(TwapError::PoolNotFound(*pool));
+        }
+        let pool_account = match self.accounts_service.get_account(pool).await {
+            Ok(account) => account,
+            Err(AccountsError::NotFound) => return Err(TwapError::PoolNotFound(*pool)),
+            Err(e) => return Err(e.into()),
+        };
+        let pool_state = PoolState::try_deserialize(&mut &pool_account[..])?;
+        let observation_account = self
+            .accounts_service
+            .get_account(&pool_state.observation_key)
+            .await?;
+        let observation_state = ObservationState::try_deserialize(&mut &observation_account[..])?;
+        let clock = self.get_cluster_clock().await?;
+        let now = u64::try_from(clock.unix_timestamp).unwrap_or_default();
+
+        let newest_index = usize::from(observation_state.observation_index);
+        let timestamps: Vec<u64> = observation_state
+            .observations
+            .iter()
+            .map(|observation| observation.block_timestamp)
+            .collect();
+        let (oldest_index, observations) =
+            observation_window(&timestamps, newest_index, now.saturating_sub(window));
+        let newest = &observation_state.observations[newest_index];
+        let oldest = &observation_state.observations[oldest_index];
+
+        let covered_window = newest
+            .block_timestamp
+            .saturating_sub(oldest.block_timestamp);
+        if covered_window == 0 {
+            return Err(TwapError::NotEnoughObservations);
+        }
+
+        let decimals_adjustment =
+            i32::from(pool_state.mint_0_decimals) - i32::from(pool_state.mint_1_decimals);
+        let token_0_twap = average_price(
+            newest
+                .cumulative_token_0_price_x32
+                .wrapping_sub(oldest.cumulative_token_0_price_x32),
+            covered_window,
+            decimals_adjustment,
+        )?;
+        let token_1_twap = average_price(
+            newest
+                .cumulative_token_1_price_x32
+                .wrapping_sub(oldest.cumulative_token_1_price_x32),
+            covered_window,
+            -decimals_adjustment,
+        )?;
+
+        let (reserve_0, reserve_1) = pool_state.vault_amount_without_fee()?;
+        let token_0_spot = spot_price(
+            (reserve_0, pool_state.mint_0_decimals),
+            (reserve_1, pool_state.mint_1_decimals),
+        )?;
+        let deviation_from_spot_pct = token_0_spot
+            .checked_sub(token_0_twap)
+            .and_then(|difference| difference.checked_div(token_0_twap))
+            .and_then(|deviation| deviation.checked_mul(Decimal::ONE_HUNDRED))
+            .ok_or(TwapError::Overflow)?;
+
+        Ok(PoolTwap {
+            pool: *pool,
+            token_0_mint: pool_state.token_0_mint,
+            token_1_mint: pool_state.token_1_mint,
+            requested_window: window,
+            covered_window,
+            observations,
+            newest_observation_age: now.saturating_sub(newest.block_timestamp),
+            token_0_twap: token_0_twap.normalize().to_string(),
+            token_1_twap: token_1_twap.normalize().to_string(),
+            token_0_spot: token_0_spot.normalize().to_string(),
+            deviation_from_spot_pct: deviation_from_spot_pct.round_dp(4).to_string(),
+        })
+    }
+}
+
+/// Walks back through a ring buffer of observation timestamps from the newest one until the
+/// window starting at `since` is covered or the buffer runs out. Returns the index of the oldest
+/// observation to use and the number of observations from it to the newest.
+fn observation_window(timestamps: &[u64], newest_index: usize, since: u64) -> (usize, usize) {
+    let len = timestamps.len();
+    let mut oldest_index = newest_index;
+    let mut observations = 1;
+    for step in 1..len {
+        let index = (newest_index + len - step) % len;
+        // Uninitialized slots have no timestamp, and older ones would run past the newest
+        if timestamps[index] == 0 || timestamps[index] >= timestamps[oldest_index] {
+            break;
+        }
+        oldest_index = index;
+        observations += 1;
+        if timestamps[index] <= since {
+            break;
+        }
+    }
+    (oldest_index, observations)
+}
+
+/// Price of one whole token0 in token1 from raw reserves. Mints can declare more decimals than
+/// a `Decimal` can scale to, which is out of range too.
+fn spot_price(
+    (reserve_0, decimals_0): (u64, u8),
+    (reserve_1, decimals_1): (u64, u8),
+) -> Result<Decimal, TwapError> {
+    let whole_tokens = |reserve: u64, decimals: u8| {
+        Decimal::try_from_i128_with_scale(i128::from(reserve), u32::from(decimals))
+            .map_err(|_| TwapError::Overflow)
+    };
+    whole_tokens(reserve_1, decimals_1)?
+        .checked_div(whole_tokens(reserve_0, decimals_0)?)
+        .ok_or(TwapError::Overflow)
+}
+
+/// Turns the growth of a Q32 cumulative price over `seconds` into an average whole-token price.
+/// `decimals_adjustment` is the base token's decimals minus the quote token's.
+fn average_price(
+    cumulative_delta_x32: u128,
+    seconds: u64,
+    decimals_adjustment: i32,
+) -> Result<Decimal, TwapError> {
+    let average_x32 = cumulative_delta_x32 / u128::from(seconds);
+    let integer = Decimal::from_u128(average_x32 / Q32).ok_or(TwapError::Overflow)?;
+    let fraction = Decimal::from_u128(average_x32 % Q32).ok_or(TwapError::Overflow)?
+        / Decimal::from(Q32 as u64);
+    let scale = Decimal::TEN
+        .checked_powi(i64::from(decimals_adjustment))
+        .ok_or(TwapError::Overflow)?;
+    integer
+        .checked_add(fraction)
+        .and_then(|price| price.checked_mul(scale))
+        .ok_or(TwapError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_stops_at_the_first_observation_before_since() {
+        let timestamps = [100, 115, 130, 145, 160, 0, 0, 0];
+        assert_eq!(observation_window(&timestamps, 4, 120), (1, 4));
+        assert_eq!(observation_window(&timestamps, 4, 130), (2, 3));
+    }
+
+    #[test]
+    fn window_stops_at_uninitialized_slots() {
+        let timestamps = [100, 115, 130, 0, 0];
+        assert_eq!(observation_window(&timestamps, 2, 0), (0, 3));
+        assert_eq!(observation_window(&[100, 0, 0], 0, 0), (0, 1));
+    }
+
+    #[test]
+    fn window_wraps_around_the_ring_buffer() {
+        // Newest is at index 1, and the buffer wrapped over the oldest entries
+        let timestamps = [190, 205, 130, 145, 160, 175];
+        assert_eq!(observation_window(&timestamps, 1, 0), (2, 6));
+        assert_eq!(observation_window(&timestamps, 1, 170), (4, 4));
+    }
+
+    #[test]
+    fn spot_price_in_whole_tokens() {
+        assert_eq!(
+            spot_price((2_000_000_000, 9), (5_000_000, 6)).unwrap(),
+            Decimal::new(25, 1)
+        );
+        assert!(matches!(
+            spot_price((1_000, 30), (1_000, 6)),
+            Err(TwapError::Overflow)
+        ));
+        assert!(matches!(
+            spot_price((0, 9), (1_000, 6)),
+            Err(TwapError::Overflow)
+        ));
+    }
+
+    #[test]
+    fn average_price_of_a_constant_price() {
+        // 2.5 in Q32 held for 10 seconds
+        let delta = (5 * Q32 / 2) * 10;
+        assert_eq!(average_price(delta, 10, 0).unwrap(), Decimal::new(25, 1));
+        assert_eq!(average_price(delta, 10, 3).unwrap(), Decimal::new(2500, 0));
+        assert_eq!(average_price(delta, 10, -2).unwrap(), Decimal::new(25, 3));
+    }
+
+    #[test]
+    fn average_price_out_of_range() {
+        assert!(matches!(
+            average_price(u128::MAX, 1, 1),
+            Err(TwapError::Overflow)
+        ));
+        assert!(matches!(
+            average_price(Q32, 1, 255),
+            Err(TwapError::Overflow)
+        ));
+    }
+}
@@ -0,0 +1,329 @@
+use super::quote::{amount_with_slippage, QuoteError};
+use super::GfxSwapClient;
+
+use solana_sdk::clock::Clock;
+use swap_api::quote::{QuoteResponse, SwapMode};
+use thiserror::Error;
+
+/// Deviation from the current market a quote can have before it's rejected in strict mode
+const DEFAULT_MAX_QUOTE_DEVIATION_BPS: u16 = 500;
+
+/// Slippage past 100% would let an ExactIn threshold floor at zero
+const MAX_SLIPPAGE_BPS: u16 = 10_000;
+
+/// How much of a posted quote the server checks before building a swap
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum QuoteVerificationLevel {
+    /// Build swaps from the posted quote as is
+    Off,
+    /// Check that the quote's threshold is within its own declared slippage
+    #[default]
+    Consistent,
+    /// Also re-run the quote on current pool state and reject quotes too far off-market
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuoteVerification {
+    pub level: QuoteVerificationLevel,
+    /// In strict mode, how far the quoted amount can be from a fresh quote
+    pub max_deviation_bps: u16,
+}
+
+impl Default for QuoteVerification {
+    fn default() -> Self {
+        QuoteVerification {
+            level: QuoteVerificationLevel::default(),
+            max_deviation_bps: DEFAULT_MAX_QUOTE_DEVIATION_BPS,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum QuoteVerificationError {
+    #[error("slippageBps {0} is more than {MAX_SLIPPAGE_BPS}")]
+    SlippageOutOfRange(u16),
+    #[error(
+        "otherAmountThreshold {threshold} is looser than {slippage_bps} bps slippage allows (limit {limit})"
+    )]
+    ThresholdTooLoose {
+        threshold: u64,
+        limit: u64,
+        slippage_bps: u16,
+    },
+    #[error(
+        "Quoted amount {quoted} is {deviation_bps} bps off the current market amount {current} (max {max_deviation_bps} bps)"
+    )]
+    OffMarket {
+        quoted: u64,
+        current: u64,
+        deviation_bps: u64,
+        max_deviation_bps: u16,
+    },
+    #[error(
+        "otherAmountThreshold {threshold} is {deviation_bps} bps off the current market limit {current_limit} (max {max_deviation_bps} bps)"
+    )]
+    ThresholdOffMarket {
+        threshold: u64,
+        current_limit: u64,
+        deviation_bps: u64,
+        max_deviation_bps: u16,
+    },
+    #[error("Failed to re-quote: {0}")]
+    Quote(#[from] QuoteError),
+}
+
+impl GfxSwapClient {
+    /// Checks a client-posted quote before a swap is built from it, to the configured level
+    pub async fn verify_quote(
+        &self,
+        quote: &QuoteResponse,
+        clock: Clock,
+    ) -> Result<(), QuoteVerificationError> {
+        if self.quote_verification.level == QuoteVerificationLevel::Off {
+            return Ok(());
+        }
+
+        check_threshold(
+            &quote.swap_mode,
+            quote.in_amount,
+            quote.out_amount,
+            quote.other_amount_threshold,
+            quote.slippage_bps,
+        )?;
+
+        if self.quote_verification.level != QuoteVerificationLevel::Strict {
+            return Ok(());
+        }
+
+        let snapshot = self
+            .load_pair_snapshot(&quote.input_mint, &quote.output_mint, clock)
+            .await?;
+        let (amount, quoted) = match quote.swap_mode {
+            SwapMode::ExactIn => (quote.in_amount, quote.out_amount),
+            SwapMode::ExactOut => (quote.out_amount, quote.in_amount),
+        };
+        let current = snapshot.compute(amount, &quote.swap_mode)?.other_amount;
+        check_against_market(
+            &quote.swap_mode,
+            quoted,
+            quote.other_amount_threshold,
+            current,
+            quote.slippage_bps,
+            self.quote_verification.max_deviation_bps,
+        )
+    }
+}
+/// The quote's threshold is no looser than its own slippage allows
+fn check_threshold(
+    swap_mode: &SwapMode,
+    in_amount: u64,
+    out_amount: u64,
+    other_amount_threshold: u64,
+    slippage_bps: u16,
+) -> Result<(), QuoteVerificationError> {
+    if slippage_bps > MAX_SLIPPAGE_BPS {
+        return Err(QuoteVerificationError::SlippageOutOfRange(slippage_bps));
+    }
+    let slippage = f64::from(slippage_bps) / 10_000.0;
+    let too_loose = |limit: u64| QuoteVerificationError::ThresholdTooLoose {
+        threshold: other_amount_threshold,
+        limit,
+        slippage_bps,
+    };
+    match swap_mode {
+        SwapMode::ExactIn => {
+            // The threshold is a minimum output
+            let limit = amount_with_slippage(out_amount, slippage, false);
+            if other_amount_threshold < limit {
+                return Err(too_loose(limit));
+            }
+        }
+        SwapMode::ExactOut => {
+            // The threshold is a maximum input
+            let limit = amount_with_slippage(in_amount, slippage, true);
+            if other_amount_threshold > limit {
+                return Err(too_loose(limit));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The quoted amount, and the threshold derived from it, are both within `max_deviation_bps` of
+/// what a fresh quote for the same amount gives. Checking the amount alone would pass a quote
+/// whose threshold was loosened to nothing.
+fn check_against_market(
+    swap_mode: &SwapMode,
+    quoted: u64,
+    other_amount_threshold: u64,
+    current: u64,
+    slippage_bps: u16,
+    max_deviation_bps: u16,
+) -> Result<(), QuoteVerificationError> {
+    let deviation = deviation_bps(quoted, current);
+    if deviation > u64::from(max_deviation_bps) {
+        return Err(QuoteVerificationError::OffMarket {
+            quoted,
+            current,
+            deviation_bps: deviation,
+            max_deviation_bps,
+        });
+    }
+
+    let slippage = f64::from(slippage_bps) / 10_000.0;
+    let current_limit = amount_with_slippage(current, slippage, *swap_mode == SwapMode::ExactOut);
+    let deviation = deviation_bps(other_amount_threshold, current_limit);
+    if deviation > u64::from(max_deviation_bps) {
+        return Err(QuoteVerificationError::ThresholdOffMarket {
+            threshold: other_amount_threshold,
+            current_limit,
+            deviation_bps: deviation,
+            max_deviation_bps,
+        });
+    }
+    Ok(())
+}
+
+/// How far `quoted` is from `current`, in bps of `current`
+fn deviation_bps(quoted: u64, current: u64) -> u64 {
+    if quoted == current {
+        return 0;
+    }
+    u128::from(quoted.abs_diff(current))
+        .saturating_mul(10_000)
+        .checked_div(u128::from(current))
+        .map_or(u64::MAX, |bps| u64::try_from(bps).unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_in_threshold_is_a_minimum_output() {
+        let check = |threshold| check_threshold(&SwapMode::ExactIn, 5_000, 10_000, threshold, 100);
+        assert!(check(9_900).is_ok());
+        assert!(check(10_000).is_ok());
+        assert!(matches!(
+            check(9_899),
+            Err(QuoteVerificationError::ThresholdTooLoose {
+                threshold: 9_899,
+                limit: 9_900,
+                slippage_bps: 100,
+            })
+        ));
+    }
+
+    #[test]
+    fn exact_out_threshold_is_a_maximum_input() {
+        let check = |threshold| check_threshold(&SwapMode::ExactOut, 10_000, 5_000, threshold, 100);
+        assert!(check(10_100).is_ok());
+        assert!(check(10_000).is_ok());
+        assert!(matches!(
+            check(10_101),
+            Err(QuoteVerificationError::ThresholdTooLoose { limit: 10_100, .. })
+        ));
+    }
+
+    #[test]
+    fn zero_slippage_requires_the_quoted_amount() {
+        assert!(check_threshold(&SwapMode::ExactIn, 1, 1_000, 1_000, 0).is_ok());
+        assert!(check_threshold(&SwapMode::ExactIn, 1, 1_000, 999, 0).is_err());
+    }
+
+    #[test]
+    fn full_slippage_is_the_most_a_threshold_can_declare() {
+        // 100% slippage floors an ExactIn minimum at zero, and doubles an ExactOut maximum
+        assert!(check_threshold(&SwapMode::ExactIn, 5_000, 10_000, 0, 10_000).is_ok());
+        assert!(check_threshold(&SwapMode::ExactOut, 10_000, 5_000, 20_000, 10_000).is_ok());
+        assert!(matches!(
+            check_threshold(&SwapMode::ExactOut, 10_000, 5_000, 20_001, 10_000),
+            Err(QuoteVerificationError::ThresholdTooLoose { limit: 20_000, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_slippage_over_100_pct() {
+        for swap_mode in [SwapMode::ExactIn, SwapMode::ExactOut] {
+            assert!(matches!(
+                check_threshold(&swap_mode, 5_000, 10_000, 0, 10_001),
+                Err(QuoteVerificationError::SlippageOutOfRange(10_001))
+            ));
+            assert!(matches!(
+                check_threshold(&swap_mode, 5_000, 10_000, 10_000, u16::MAX),
+                Err(QuoteVerificationError::SlippageOutOfRange(u16::MAX))
+            ));
+        }
+    }
+
+    #[test]
+    fn strict_mode_bounds_the_quoted_amount() {
+        let check =
+            |quoted| check_against_market(&SwapMode::ExactIn, quoted, quoted, 10_000, 0, 500);
+        assert!(check(10_500).is_ok());
+        assert!(check(9_500).is_ok());
+        assert!(matches!(
+            check(9_499),
+            Err(QuoteVerificationError::OffMarket {
+                quoted: 9_499,
+                current: 10_000,
+                deviation_bps: 501,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn strict_mode_bounds_the_threshold_against_the_fresh_limit() {
+        // A fresh quote gives 10_000 out, so 100 bps slippage limits the output to 9_900
+        let exact_in = |threshold| {
+            check_against_market(&SwapMode::ExactIn, 10_000, threshold, 10_000, 100, 500)
+        };
+        assert!(exact_in(9_900).is_ok());
+        assert!(exact_in(9_405).is_ok());
+        assert!(matches!(
+            exact_in(9_404),
+            Err(QuoteVerificationError::ThresholdOffMarket {
+                current_limit: 9_900,
+                ..
+            })
+        ));
+        // A zeroed threshold is off-market however close the quoted amount is
+        assert!(matches!(
+            check_against_market(&SwapMode::ExactIn, 10_000, 0, 10_000, 100, 500),
+            Err(QuoteVerificationError::ThresholdOffMarket { .. })
+        ));
+
+        // A fresh quote needs 10_000 in, so 100 bps slippage limits the input to 10_100
+        let exact_out = |threshold| {
+            check_against_market(&SwapMode::ExactOut, 10_000, threshold, 10_000, 100, 500)
+        };
+        assert!(exact_out(10_100).is_ok());
+        assert!(exact_out(10_605).is_ok());
+        assert!(matches!(
+            exact_out(10_700),
+            Err(QuoteVerificationError::ThresholdOffMarket {
+                current_limit: 10_100,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn deviation_is_relative_to_the_current_amount() {
+        assert_eq!(deviation_bps(10_000, 10_000), 0);
+        assert_eq!(deviation_bps(10_500, 10_000), 500);
+        assert_eq!(deviation_bps(9_500, 10_000), 500);
+        assert_eq!(deviation_bps(1, 0), u64::MAX);
+        assert_eq!(deviation_bps(0, 0), 0);
+        assert_eq!(deviation_bps(u64::MAX, 1), u64::MAX);
+    }
+
+    #[test]
+    fn default_verification_is_consistent_with_a_5_pct_market_bound() {
+        let verification = QuoteVerification::default();
+        assert_eq!(verification.level, QuoteVerificationLevel::Consistent);
+        assert_eq!(verification.max_deviation_bps, 500);
+    }
+}
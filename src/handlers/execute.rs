@@ -0,0 +1,35 @@
+use crate::gfx_swap::execute::{ExecuteError, ExecuteRequest, ExecuteResponse};
+use crate::gfx_swap::GfxSwapClient;
+use crate::handlers::{ApiResponse, ErrorResponse};
+
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+};
+use log::error;
+
+pub async fn execute(
+    State(gfx_swap): State<GfxSwapClient>,
+    Json(params): Json<ExecuteRequest>,
+) -> (StatusCode, Json<ApiResponse<ExecuteResponse>>) {
+    match gfx_swap.execute(params).await {
+        Ok(response) => (StatusCode::OK, Json(ApiResponse::T(response))),
+        Err(e) => {
+            let status = match e {
+                ExecuteError::InvalidTransaction(_)
+                | ExecuteError::MissingTip
+                | ExecuteError::NotAGammaTransaction(_) => StatusCode::BAD_REQUEST,
+                ExecuteError::BlockEngine(_) => StatusCode::BAD_GATEWAY,
+            };
+            if status.is_server_error() {
+                error!("execute error: {}", e);
+            }
+            (
+                status,
+                Json(ApiResponse::Error(ErrorResponse {
+                    message: e.to_string(),
+                })),
+            )
+        }
+    }
+}
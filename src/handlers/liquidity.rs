@@ -0,0 +1,51 @@
+use crate::gfx_swap::liquidity::{LiquidityError, LiquidityRequest, LiquidityResponse};
+use crate::gfx_swap::GfxSwapClient;
+use crate::handlers::{ApiResponse, ErrorResponse};
+
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+};
+use log::error;
+
+pub async fn deposit(
+    State(gfx_swap): State<GfxSwapClient>,
+    Json(params): Json<LiquidityRequest>,
+) -> (StatusCode, Json<ApiResponse<LiquidityResponse>>) {
+    respond(gfx_swap.deposit_transaction(params).await)
+}
+
+pub async fn withdraw(
+    State(gfx_swap): State<GfxSwapClient>,
+    Json(params): Json<LiquidityRequest>,
+) -> (StatusCode, Json<ApiResponse<LiquidityResponse>>) {
+    respond(gfx_swap.withdraw_transaction(params).await)
+}
+
+fn respond(
+    result: Result<LiquidityResponse, LiquidityError>,
+) -> (StatusCode, Json<ApiResponse<LiquidityResponse>>) {
+    match result {
+        Ok(response) => (StatusCode::OK, Json(ApiResponse::T(response))),
+        Err(e) => {
+            let status = match e {
+                LiquidityError::InvalidRequest(_)
+                | LiquidityError::UnknownPool(_)
+                | LiquidityError::DepositDisabled
+                | LiquidityError::WithdrawDisabled
+                | LiquidityError::NoPosition(..)
+                | LiquidityError::TokenPolicy(_) => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            if status.is_server_error() {
+                error!("liquidity error: {}", e);
+            }
+            (
+                status,
+                Json(ApiResponse::Error(ErrorResponse {
+                    message: e.to_string(),
+                })),
+            )
+        }
+    }
+}
@@ -1,4 +1,11 @@
+pub mod execute;
+pub mod liquidity;
+pub mod network;
+pub mod pools;
+pub mod price;
 pub mod quote;
+pub mod quote_stream;
+pub mod referral;
 pub mod swap;
 
 use serde::Serialize;
@@ -0,0 +1,30 @@
+use crate::blockhash_polling::BlockhashReport;
+use crate::gfx_swap::GfxSwapClient;
+use crate::handlers::{ApiResponse, ErrorResponse};
+use crate::priofee::PriorityFeesReport;
+
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+};
+
+pub async fn priority_fees(
+    State(gfx_swap): State<GfxSwapClient>,
+) -> (StatusCode, Json<ApiResponse<PriorityFeesReport>>) {
+    match &gfx_swap.priofees_handle {
+        Some(handle) => (StatusCode::OK, Json(ApiResponse::T(handle.report().await))),
+        None => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ApiResponse::Error(ErrorResponse {
+                message: "Priority fee estimates are unavailable".to_string(),
+            })),
+        ),
+    }
+}
+
+pub async fn blockhash(
+    State(gfx_swap): State<GfxSwapClient>,
+) -> (StatusCode, Json<ApiResponse<BlockhashReport>>) {
+    let report = gfx_swap.blockhash.read().await.report();
+    (StatusCode::OK, Json(ApiResponse::T(report)))
+}
@@ -0,0 +1,62 @@
+use crate::gfx_swap::pools::PoolInfo;
+use crate::gfx_swap::twap::{PoolTwap, TwapError, TwapRequest};
+use crate::gfx_swap::GfxSwapClient;
+use crate::handlers::{ApiResponse, ErrorResponse};
+use std::str::FromStr;
+
+use axum::{
+    extract::{Json, Path, Query, State},
+    http::StatusCode,
+};
+use log::error;
+use solana_sdk::pubkey::Pubkey;
+
+pub async fn pools(
+    State(gfx_swap): State<GfxSwapClient>,
+) -> (StatusCode, Json<ApiResponse<Vec<PoolInfo>>>) {
+    match gfx_swap.pools().await {
+        Ok(pools) => (StatusCode::OK, Json(ApiResponse::T(pools))),
+        Err(e) => {
+            error!("Error getting pools: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::Error(ErrorResponse {
+                    message: e.to_string(),
+                })),
+            )
+        }
+    }
+}
+
+pub async fn twap(
+    State(gfx_swap): State<GfxSwapClient>,
+    Path(pool): Path<String>,
+    Query(params): Query<TwapRequest>,
+) -> (StatusCode, Json<ApiResponse<PoolTwap>>) {
+    let result = match Pubkey::from_str(&pool) {
+        Ok(pool) => gfx_swap.twap(&pool, &params).await,
+        Err(_) => Err(TwapError::InvalidRequest(format!("Invalid pool: {}", pool))),
+    };
+    match result {
+        Ok(twap) => (StatusCode::OK, Json(ApiResponse::T(twap))),
+        Err(e) => {
+            let status = match e {
+                // Out-of-range prices come from the pool's own mints and reserves
+                TwapError::InvalidRequest(_)
+                | TwapError::NotEnoughObservations
+                | TwapError::Overflow => StatusCode::BAD_REQUEST,
+                TwapError::PoolNotFound(_) => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            if status.is_server_error() {
+                error!("Error getting twap: {}", e);
+            }
+            (
+                status,
+                Json(ApiResponse::Error(ErrorResponse {
+                    message: e.to_string(),
+                })),
+            )
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use crate::gfx_swap::price::{PriceRequest, PriceResponse};
+use crate::gfx_swap::GfxSwapClient;
+use crate::handlers::{ApiResponse, ErrorResponse};
+
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+};
+
+pub async fn price(
+    State(gfx_swap): State<GfxSwapClient>,
+    Query(params): Query<PriceRequest>,
+) -> (StatusCode, Json<ApiResponse<PriceResponse>>) {
+    match gfx_swap.price(&params).await {
+        Ok(prices) => (StatusCode::OK, Json(ApiResponse::T(prices))),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::Error(ErrorResponse {
+                message: e.to_string(),
+            })),
+        ),
+    }
+}
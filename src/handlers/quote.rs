@@ -1,4 +1,5 @@
-use crate::gfx_swap::quote::QuoteError;
+use crate::gfx_swap::ladder::{LadderRequest, QuoteLadder};
+use crate::gfx_swap::quote::{GammaQuoteResponse, QuoteError, QuoteOptions};
 use crate::gfx_swap::GfxSwapClient;
 use crate::handlers::{ApiResponse, ErrorResponse};
 
@@ -7,22 +8,22 @@ use axum::{
     http::StatusCode,
 };
 use log::error;
-use swap_api::quote::{QuoteRequest, QuoteResponse};
+use swap_api::quote::QuoteRequest;
 
 pub async fn quote(
     State(gfx_swap): State<GfxSwapClient>,
     Query(params): Query<QuoteRequest>,
-) -> (StatusCode, Json<ApiResponse<QuoteResponse>>) {
-    match gfx_swap.quote(&params).await {
+    Query(options): Query<QuoteOptions>,
+) -> (StatusCode, Json<ApiResponse<GammaQuoteResponse>>) {
+    match gfx_swap.quote_with_options(&params, &options).await {
         Ok(quote) => (StatusCode::OK, Json(ApiResponse::T(quote))),
-        Err(QuoteError::InvalidRequest(message)) => (
-            StatusCode::BAD_REQUEST,
-            Json(ApiResponse::Error(ErrorResponse { message })),
-        ),
         Err(e) => {
-            error!("Error getting quote: {}", e);
+            let status = quote_error_status(&e);
+            if status.is_server_error() {
+                error!("Error getting quote: {}", e);
+            }
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                status,
                 Json(ApiResponse::Error(ErrorResponse {
                     message: e.to_string(),
                 })),
@@ -30,3 +31,77 @@ pub async fn quote(
         }
     }
 }
+
+pub async fn quote_batch(
+    State(gfx_swap): State<GfxSwapClient>,
+    Query(options): Query<QuoteOptions>,
+    Json(params): Json<Vec<QuoteRequest>>,
+) -> (
+    StatusCode,
+    Json<ApiResponse<Vec<ApiResponse<GammaQuoteResponse>>>>,
+) {
+    match gfx_swap.quote_batch(&params, &options).await {
+        Ok(results) => {
+            let quotes = results
+                .into_iter()
+                .map(|result| match result {
+                    Ok(quote) => ApiResponse::T(quote),
+                    Err(e) => {
+                        if quote_error_status(&e).is_server_error() {
+                            error!("Error getting quote in batch: {}", e);
+                        }
+                        ApiResponse::Error(ErrorResponse {
+                            message: e.to_string(),
+                        })
+                    }
+                })
+                .collect();
+            (StatusCode::OK, Json(ApiResponse::T(quotes)))
+        }
+        Err(e) => {
+            let status = quote_error_status(&e);
+            if status.is_server_error() {
+                error!("Error getting quote batch: {}", e);
+            }
+            (
+                status,
+                Json(ApiResponse::Error(ErrorResponse {
+                    message: e.to_string(),
+                })),
+            )
+        }
+    }
+}
+
+pub async fn quote_ladder(
+    State(gfx_swap): State<GfxSwapClient>,
+    Query(params): Query<LadderRequest>,
+) -> (StatusCode, Json<ApiResponse<QuoteLadder>>) {
+    match gfx_swap.quote_ladder(&params).await {
+        Ok(ladder) => (StatusCode::OK, Json(ApiResponse::T(ladder))),
+        Err(e) => {
+            let status = quote_error_status(&e);
+            if status.is_server_error() {
+                error!("Error getting quote ladder: {}", e);
+            }
+            (
+                status,
+                Json(ApiResponse::Error(ErrorResponse {
+                    message: e.to_string(),
+                })),
+            )
+        }
+    }
+}
+
+pub fn quote_error_status(e: &QuoteError) -> StatusCode {
+    match e {
+        QuoteError::InvalidRequest(_)
+        | QuoteError::TokenPolicy(_)
+        | QuoteError::PairNotTradeable
+        | QuoteError::PoolDisabled
+        | QuoteError::PoolNotOpen { .. }
+        | QuoteError::AmountOutOfRange(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
@@ -0,0 +1,118 @@
+use crate::accounts::AccountsGetter;
+use crate::gfx_swap::quote::{GammaQuoteResponse, QuoteOptions};
+use crate::gfx_swap::GfxSwapClient;
+use crate::handlers::quote::quote_error_status;
+use crate::handlers::{ApiResponse, ErrorResponse};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::Response,
+};
+use log::error;
+use solana_sdk::pubkey::Pubkey;
+use swap_api::quote::QuoteRequest;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Streams quotes over a websocket. The client sends a `QuoteRequest` as a JSON text message, and
+/// gets a quote right away and again whenever the pool, observation or config account behind it
+/// changes. Sending another request replaces the subscription.
+pub async fn quote_stream(
+    State(gfx_swap): State<GfxSwapClient>,
+    Query(options): Query<QuoteOptions>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| handle_quote_stream(gfx_swap, options, socket))
+}
+
+async fn handle_quote_stream(
+    gfx_swap: GfxSwapClient,
+    options: QuoteOptions,
+    mut socket: WebSocket,
+) {
+    let Some(mut updates) = gfx_swap.accounts_service.subscribe_updates() else {
+        _ = send_error(
+            &mut socket,
+            "Quote streaming is not supported by this server",
+        )
+        .await;
+        return;
+    };
+
+    let mut subscription: Option<QuoteRequest> = None;
+    let mut dependencies: Vec<Pubkey> = vec![];
+    loop {
+        let push = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(request) => {
+                        subscription = Some(request);
+                        true
+                    }
+                    Err(e) => {
+                        let message = format!("Invalid quote request: {}", e);
+                        if send_error(&mut socket, &message).await.is_err() {
+                            break;
+                        }
+                        false
+                    }
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Pings are answered by axum
+                Some(Ok(_)) => false,
+            },
+            update = updates.recv() => match update {
+                Ok(pubkey) => dependencies.contains(&pubkey),
+                // Some notifications were dropped, so one of them may have been ours
+                Err(RecvError::Lagged(_)) => true,
+                Err(RecvError::Closed) => break,
+            },
+        };
+
+        let Some(request) = subscription.as_ref().filter(|_| push) else {
+            continue;
+        };
+        dependencies = gfx_swap
+            .quote_dependencies(&request.input_mint, &request.output_mint)
+            .await;
+        let quote = match gfx_swap.get_recent_cluster_clock().await {
+            Ok(clock) => {
+                gfx_swap
+                    .quote_with_options_at(request, &options, clock)
+                    .await
+            }
+            Err(e) => Err(e.into()),
+        };
+        let response = match quote {
+            Ok(quote) => ApiResponse::<GammaQuoteResponse>::T(quote),
+            Err(e) => {
+                if quote_error_status(&e).is_server_error() {
+                    error!("Error getting streamed quote: {}", e);
+                }
+                ApiResponse::Error(ErrorResponse {
+                    message: e.to_string(),
+                })
+            }
+        };
+        if send_json(&mut socket, &response).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn send_error(socket: &mut WebSocket, message: &str) -> Result<(), axum::Error> {
+    let response = ApiResponse::<()>::Error(ErrorResponse {
+        message: message.to_string(),
+    });
+    send_json(socket, &response).await
+}
+
+async fn send_json<T: serde::Serialize>(
+    socket: &mut WebSocket,
+    response: &ApiResponse<T>,
+) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(response).map_err(axum::Error::new)?;
+    socket.send(Message::Text(text)).await
+}
@@ -0,0 +1,72 @@
+use crate::gfx_swap::referral::{
+    InitializeReferralAccountRequest, InitializeReferralAccountResponse,
+    InitializeReferralTokenAccountsRequest, InitializeReferralTokenAccountsResponse, ReferralError,
+    ReferralFeesReport,
+};
+use crate::gfx_swap::GfxSwapClient;
+use crate::handlers::{ApiResponse, ErrorResponse};
+use std::str::FromStr;
+
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use log::error;
+use solana_sdk::pubkey::Pubkey;
+
+pub async fn initialize_account(
+    State(gfx_swap): State<GfxSwapClient>,
+    Json(params): Json<InitializeReferralAccountRequest>,
+) -> (
+    StatusCode,
+    Json<ApiResponse<InitializeReferralAccountResponse>>,
+) {
+    respond(gfx_swap.initialize_referral_account(params).await)
+}
+
+pub async fn initialize_token_accounts(
+    State(gfx_swap): State<GfxSwapClient>,
+    Json(params): Json<InitializeReferralTokenAccountsRequest>,
+) -> (
+    StatusCode,
+    Json<ApiResponse<InitializeReferralTokenAccountsResponse>>,
+) {
+    respond(gfx_swap.initialize_referral_token_accounts(params).await)
+}
+
+pub async fn fees(
+    State(gfx_swap): State<GfxSwapClient>,
+    Path(account): Path<String>,
+) -> (StatusCode, Json<ApiResponse<ReferralFeesReport>>) {
+    let result = match Pubkey::from_str(&account) {
+        Ok(account) => gfx_swap.referral_fees(&account).await,
+        Err(_) => Err(ReferralError::InvalidRequest(format!(
+            "Invalid referral account: {}",
+            account
+        ))),
+    };
+    respond(result)
+}
+
+fn respond<T>(result: Result<T, ReferralError>) -> (StatusCode, Json<ApiResponse<T>>) {
+    match result {
+        Ok(response) => (StatusCode::OK, Json(ApiResponse::T(response))),
+        Err(e) => {
+            let status = match e {
+                ReferralError::InvalidRequest(_) | ReferralError::NotAReferralAccount(..) => {
+                    StatusCode::BAD_REQUEST
+                }
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            if status.is_server_error() {
+                error!("referral error: {}", e);
+            }
+            (
+                status,
+                Json(ApiResponse::Error(ErrorResponse {
+                    message: e.to_string(),
+                })),
+            )
+        }
+    }
+}
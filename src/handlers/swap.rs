@@ -1,5 +1,9 @@
-use crate::gfx_swap::swap::SwapError;
+use crate::gfx_swap::swap::{
+    GammaSwapInstructionsResponse, GammaSwapRequest, GammaSwapResponse, SwapError,
+};
+use crate::gfx_swap::verify::QuoteVerificationError;
 use crate::gfx_swap::GfxSwapClient;
+use crate::handlers::quote::quote_error_status;
 use crate::handlers::{ApiResponse, ErrorResponse};
 
 use axum::{
@@ -7,25 +11,24 @@ use axum::{
     http::StatusCode,
 };
 use log::error;
-use swap_api::swap::{SwapInstructionsResponseInternal, SwapRequest, SwapResponse};
 
 pub async fn swap_instructions(
     State(gfx_swap): State<GfxSwapClient>,
-    Json(params): Json<SwapRequest>,
-) -> (
-    StatusCode,
-    Json<ApiResponse<SwapInstructionsResponseInternal>>,
-) {
-    match gfx_swap.swap_instructions(&params).await {
+    Json(params): Json<GammaSwapRequest>,
+) -> (StatusCode, Json<ApiResponse<GammaSwapInstructionsResponse>>) {
+    let result = match gfx_swap.resolve_swap_request(params).await {
+        Ok(params) => gfx_swap.swap_instructions(&params).await,
+        Err(e) => Err(e),
+    };
+    match result {
         Ok(quote) => (StatusCode::OK, Json(ApiResponse::T(quote))),
-        Err(SwapError::InvalidRequest(message)) => (
-            StatusCode::BAD_REQUEST,
-            Json(ApiResponse::Error(ErrorResponse { message })),
-        ),
         Err(e) => {
-            error!("swap-instructions error: {}", e);
+            let status = swap_error_status(&e);
+            if status.is_server_error() {
+                error!("swap-instructions error: {}", e);
+            }
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                status,
                 Json(ApiResponse::Error(ErrorResponse {
                     message: e.to_string(),
                 })),
@@ -36,18 +39,21 @@ pub async fn swap_instructions(
 
 pub async fn swap_transaction(
     State(gfx_swap): State<GfxSwapClient>,
-    Json(params): Json<SwapRequest>,
-) -> (StatusCode, Json<ApiResponse<SwapResponse>>) {
-    match gfx_swap.swap_transaction(&params).await {
+    Json(params): Json<GammaSwapRequest>,
+) -> (StatusCode, Json<ApiResponse<GammaSwapResponse>>) {
+    let result = match gfx_swap.resolve_swap_request(params).await {
+        Ok(params) => gfx_swap.swap_transaction(&params).await,
+        Err(e) => Err(e),
+    };
+    match result {
         Ok(quote) => (StatusCode::OK, Json(ApiResponse::T(quote))),
-        Err(SwapError::InvalidRequest(message)) => (
-            StatusCode::BAD_REQUEST,
-            Json(ApiResponse::Error(ErrorResponse { message })),
-        ),
         Err(e) => {
-            error!("swap-transaction error: {}", e);
+            let status = swap_error_status(&e);
+            if status.is_server_error() {
+                error!("swap-transaction error: {}", e);
+            }
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                status,
                 Json(ApiResponse::Error(ErrorResponse {
                     message: e.to_string(),
                 })),
@@ -55,3 +61,27 @@ pub async fn swap_transaction(
         }
     }
 }
+
+fn swap_error_status(e: &SwapError) -> StatusCode {
+    match e {
+        SwapError::InvalidRequest(_)
+        | SwapError::TokenPolicy(_)
+        | SwapError::PairNotTradeable
+        | SwapError::PoolDisabled
+        | SwapError::PoolNotOpen { .. } => StatusCode::BAD_REQUEST,
+        SwapError::QuoteVerification(QuoteVerificationError::Quote(e)) => quote_error_status(e),
+        SwapError::QuoteVerification(_)
+        | SwapError::UnknownQuote(_)
+        | SwapError::QuoteExpired { .. }
+        | SwapError::InvalidQuoteSignature
+        | SwapError::InvalidNonceAccount(_)
+        | SwapError::SponsorUnavailable
+        | SwapError::SponsorLimitExceeded { .. }
+        | SwapError::InsufficientBalance { .. }
+        | SwapError::InputAccountFrozen(_)
+        | SwapError::InvalidDestinationAccount(_)
+        | SwapError::ReferralNotAllowed(_) => StatusCode::BAD_REQUEST,
+        SwapError::JitoUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
@@ -0,0 +1,165 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use log::{info, warn};
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use serde_json::json;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_sdk::{pubkey, pubkey::Pubkey};
+use tokio::sync::RwLock;
+
+use crate::utils::json_rpc_request;
+
+// https://docs.jito.wtf/lowlatencytxnsend/
+
+pub const DEFAULT_BLOCK_ENGINE_URL: &str = "https://mainnet.block-engine.jito.wtf";
+pub const DEFAULT_TIP_FLOOR_URL: &str = "https://bundles.jito.wtf/api/v1/bundles/tip_floor";
+/// JSON-RPC endpoint of the block engine's bundle methods
+const BUNDLES_PATH: &str = "/api/v1/bundles";
+/// Tip accounts rarely change, and `getTipAccounts` is rate-limited
+const TIP_ACCOUNTS_REFRESH: Duration = Duration::from_secs(600);
+const TIP_FLOOR_REFRESH: Duration = Duration::from_secs(10);
+/// Block engines don't forward bundles that tip less than this
+pub const MIN_TIP_LAMPORTS: u64 = 1_000;
+/// Mainnet's tip accounts, used against the default block engine until they are first fetched
+pub const MAINNET_TIP_ACCOUNTS: [Pubkey; 8] = [
+    pubkey!("96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5"),
+    pubkey!("HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe"),
+    pubkey!("Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY"),
+    pubkey!("ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49"),
+    pubkey!("DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh"),
+    pubkey!("ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt"),
+    pubkey!("DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL"),
+    pubkey!("3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT"),
+];
+
+/// Client for a Jito block engine, with its tip accounts and the tips recent bundles landed with
+#[derive(Clone)]
+pub struct JitoClient {
+    bundles_url: String,
+    tip_accounts: Arc<RwLock<Vec<Pubkey>>>,
+    tip_floor: Arc<RwLock<Option<TipFloor>>>,
+}
+
+/// The tip floor endpoint's percentiles of tips in landed bundles, in SOL
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct TipFloor {
+    ema_landed_tips_50th_percentile: f64,
+}
+
+impl JitoClient {
+    /// One of the block engine's tip accounts, picked at random to spread write-locks. `None`
+    /// until they are first fetched, unless the engine is the default one.
+    pub async fn random_tip_account(&self) -> Option<Pubkey> {
+        self.tip_accounts
+            .read()
+            .await
+            .choose(&mut rand::thread_rng())
+            .copied()
+    }
+
+    pub async fn is_tip_account(&self, account: &Pubkey) -> bool {
+        self.tip_accounts.read().await.contains(account)
+    }
+
+    /// A tip that lands bundles about half the time: the moving average of the median landed tip
+    pub async fn auto_tip_lamports(&self) -> Option<u64> {
+        let tip_floor = (*self.tip_floor.read().await)?;
+        let tip = (tip_floor.ema_landed_tips_50th_percentile * LAMPORTS_PER_SOL as f64).ceil();
+        Some(std::cmp::max(MIN_TIP_LAMPORTS, tip as u64))
+    }
+
+    /// Submits signed transactions as a bundle, returning its ID
+    pub async fn send_bundle(
+        &self,
+        transactions: &[VersionedTransaction],
+    ) -> anyhow::Result<String> {
+        let encoded = transactions
+            .iter()
+            .map(|transaction| Ok(STANDARD.encode(bincode::serialize(transaction)?)))
+            .collect::<anyhow::Result<Vec<String>>>()?;
+        json_rpc_request(
+            &self.bundles_url,
+            "sendBundle",
+            json!([encoded, { "encoding": "base64" }]),
+        )
+        .await
+    }
+
+    async fn fetch_tip_accounts(&self) -> anyhow::Result<Vec<Pubkey>> {
+        let accounts: Vec<String> =
+            json_rpc_request(&self.bundles_url, "getTipAccounts", json!([])).await?;
+        accounts
+            .iter()
+            .map(|account| {
+                account
+                    .parse()
+                    .map_err(|e| anyhow!("Invalid tip account {account}: {e}"))
+            })
+            .collect()
+    }
+}
+
+async fn fetch_tip_floor(url: &str) -> anyhow::Result<TipFloor> {
+    let tip_floors: Vec<TipFloor> = reqwest::get(url).await?.error_for_status()?.json().await?;
+    tip_floors
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Empty tip floor response"))
+}
+
+/// Keeps the block engine's tip accounts and the tip floor up to date. Against the default
+/// block engine, tips go to mainnet's known tip accounts until the fetch succeeds.
+pub fn start_jito_task(
+    block_engine_url: &str,
+    tip_floor_url: String,
+) -> (JitoClient, tokio::task::JoinHandle<anyhow::Result<()>>) {
+    let block_engine_url = block_engine_url.trim_end_matches('/');
+    let fallback_tip_accounts = if block_engine_url == DEFAULT_BLOCK_ENGINE_URL {
+        MAINNET_TIP_ACCOUNTS.to_vec()
+    } else {
+        vec![]
+    };
+    let client = JitoClient {
+        bundles_url: format!("{}{}", block_engine_url, BUNDLES_PATH),
+        tip_accounts: Arc::new(RwLock::new(fallback_tip_accounts)),
+        tip_floor: Arc::default(),
+    };
+    let task = tokio::spawn({
+        let client = client.clone();
+        async move {
+            let mut interval = tokio::time::interval(TIP_FLOOR_REFRESH);
+            let mut tip_accounts_updated_at: Option<Instant> = None;
+            loop {
+                interval.tick().await;
+                let tip_accounts_fresh = matches!(
+                    tip_accounts_updated_at,
+                    Some(at) if at.elapsed() < TIP_ACCOUNTS_REFRESH
+                );
+                if !tip_accounts_fresh {
+                    match client.fetch_tip_accounts().await {
+                        Ok(accounts) if !accounts.is_empty() => {
+                            if tip_accounts_updated_at.is_none() {
+                                info!("Fetched {} Jito tip accounts", accounts.len());
+                            }
+                            *client.tip_accounts.write().await = accounts;
+                            tip_accounts_updated_at = Some(Instant::now());
+                        }
+                        Ok(_) => warn!("Block engine returned no tip accounts"),
+                        Err(e) => warn!("Failed to fetch Jito tip accounts: {}", e),
+                    }
+                }
+                match fetch_tip_floor(&tip_floor_url).await {
+                    Ok(tip_floor) => *client.tip_floor.write().await = Some(tip_floor),
+                    Err(e) => warn!("Failed to fetch Jito tip floor: {}", e),
+                }
+            }
+        }
+    });
+    (client, task)
+}
@@ -2,6 +2,7 @@ pub mod accounts;
 pub mod blockhash_polling;
 pub mod gfx_swap;
 pub mod handlers;
+pub mod jito;
 pub mod priofee;
 pub mod tx_utils;
 pub mod utils;
@@ -5,10 +5,19 @@ use axum::{
     routing::{get, post},
     Router,
 };
-use blockhash_polling::{get_blockhash_data_with_retry, start_blockhash_polling_task};
+use blockhash_polling::{
+    get_blockhash_data_with_retry, start_blockhash_polling_task, start_grpc_blockhash_task,
+};
 use clap::Parser;
+use gfx_swap::quote_cache::QuoteCache;
+use gfx_swap::sponsor::Sponsor;
+use gfx_swap::token_policy::{TokenExtension, TokenExtensionPolicy};
+use gfx_swap::verify::{QuoteVerification, QuoteVerificationLevel};
 use gfx_swap::GfxSwapClient;
-use priofee::start_priofees_task;
+use priofee::helius::HeliusProvider;
+use priofee::quicknode::QuickNodeProvider;
+use priofee::rpc::RpcProvider;
+use priofee::{start_priofees_task, PriorityFeeProvider, PriorityFeeProviderKind};
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::pubkey::Pubkey;
@@ -22,6 +31,7 @@ mod accounts;
 mod blockhash_polling;
 mod gfx_swap;
 mod handlers;
+mod jito;
 mod priofee;
 mod tx_utils;
 mod utils;
@@ -51,7 +61,19 @@ pub struct Opts {
     )]
     blockhash_poll_frequency_ms: Option<u64>,
 
-    #[clap(long, env, help = "The URL to make priority fee requests to")]
+    #[clap(
+        long,
+        env,
+        value_enum,
+        help = "Where to get priority fee estimates: quicknode, helius or rpc (getRecentPrioritizationFees). Defaults to quicknode with a priofee-url, rpc otherwise"
+    )]
+    priofee_provider: Option<PriorityFeeProviderKind>,
+
+    #[clap(
+        long,
+        env,
+        help = "The URL to make priority fee requests to. Defaults to the RPC-URL"
+    )]
     priofee_url: Option<String>,
 
     #[clap(
@@ -70,6 +92,93 @@ pub struct Opts {
     #[clap(long, env, help = "The referral program")]
     referral_program: Option<Pubkey>,
 
+    #[clap(
+        long,
+        env,
+        value_delimiter = ',',
+        help = "Comma-separated referral accounts swap requests can share fees with"
+    )]
+    allowed_referral_accounts: Option<Vec<Pubkey>>,
+
+    #[clap(
+        long,
+        env,
+        help = "The referral program project new referral accounts are created under"
+    )]
+    referral_project: Option<Pubkey>,
+
+    #[clap(
+        long,
+        env,
+        value_enum,
+        value_delimiter = ',',
+        help = "Comma-separated token-2022 mint extensions allowed in quotes and swaps"
+    )]
+    allowed_token_extensions: Option<Vec<TokenExtension>>,
+
+    #[clap(
+        long,
+        env,
+        value_enum,
+        help = "How posted quotes are checked before building swaps. Defaults to `consistent`"
+    )]
+    quote_verification: Option<QuoteVerificationLevel>,
+
+    #[clap(
+        long,
+        env,
+        help = "How far(in bps) a quote can be off the current market in strict verification"
+    )]
+    max_quote_deviation_bps: Option<u16>,
+
+    #[clap(
+        long,
+        env,
+        help = "Key for signing quote IDs. A random key is used if not set, so signed quotes don't survive restarts"
+    )]
+    quote_signing_key: Option<String>,
+
+    #[clap(long, env, help = "How many slots a quote ID stays valid for")]
+    quote_ttl_slots: Option<u64>,
+
+    #[clap(long, env, help = "How many quotes are kept for swapping by quote ID")]
+    quote_cache_size: Option<usize>,
+
+    #[clap(
+        long,
+        env,
+        help = "Path to a keypair that pays fees and ATA rent for sponsored swaps. Sponsored swaps are disabled without it"
+    )]
+    sponsor_keypair: Option<String>,
+
+    #[clap(
+        long,
+        env,
+        help = "Most a single sponsored swap can cost the sponsor, in lamports"
+    )]
+    sponsor_max_lamports_per_swap: Option<u64>,
+
+    #[clap(
+        long,
+        env,
+        help = "Jito block engine for tip accounts and bundles. Defaults to mainnet's"
+    )]
+    jito_block_engine_url: Option<String>,
+
+    #[clap(
+        long,
+        env,
+        help = "Where to get the tips recent Jito bundles landed with, for auto tips. Defaults to Jito's bundles API"
+    )]
+    jito_tip_floor_url: Option<String>,
+
+    #[clap(
+        long,
+        env,
+        help = "Check balances and token accounts before building swaps, unless a request sets preflightChecks"
+    )]
+    preflight_checks: bool,
+
     #[clap(subcommand)]
     mode: Mode,
 }
@@ -96,35 +205,29 @@ async fn main() -> anyhow::Result<()> {
     env_logger::init();
     let opts = Opts::parse();
 
-    let rpc_client = Arc::new(RpcClient::new(opts.rpc_url));
+    let rpc_client = Arc::new(RpcClient::new(opts.rpc_url.clone()));
     let commitment_config = CommitmentConfig::confirmed();
     let blockhash = Arc::new(RwLock::new(
         get_blockhash_data_with_retry(&rpc_client, commitment_config, 3).await?,
     ));
     let mut tasks = vec![];
+    // With gRPC, blockhashes are streamed and polling only covers for the stream being down
+    if let Mode::UseGrpc { addr, x_token } = &opts.mode {
+        tasks.push(start_grpc_blockhash_task(
+            addr.clone(),
+            x_token.clone(),
+            Arc::clone(&blockhash),
+        ));
+    }
     let blockhash_task = start_blockhash_polling_task(
         Arc::clone(&rpc_client),
         Arc::clone(&blockhash),
         commitment_config,
         opts.blockhash_poll_frequency_ms.map(Duration::from_millis),
+        matches!(opts.mode, Mode::UseGrpc { .. }),
     );
     tasks.push(blockhash_task);
 
-    let priofees_handle = match opts.priofee_url {
-        Some(url) => {
-            let (handle, task) = start_priofees_task(
-                url,
-                opts.priofee_n_blocks,
-                Some(opts.amm_program_id.to_string()),
-                opts.priofee_poll_frequency_secs.map(Duration::from_secs),
-            )
-            .await?;
-            tasks.push(task);
-            Some(handle)
-        }
-        None => None,
-    };
-
     let store = Arc::new(accounts::MemStore::default());
     let (amm_pools, amm_pools_task, accounts_store, accounts_updater_task) = match opts.mode {
         Mode::UseGrpc { addr, x_token } => {
@@ -183,21 +286,122 @@ async fn main() -> anyhow::Result<()> {
     .await?;
     tasks.push(account_service_task);
 
+    let priofee_provider = opts.priofee_provider.unwrap_or(match opts.priofee_url {
+        Some(_) => PriorityFeeProviderKind::Quicknode,
+        None => PriorityFeeProviderKind::Rpc,
+    });
+    let priofee_url = opts.priofee_url.unwrap_or_else(|| opts.rpc_url.clone());
+    let priofee_provider: Arc<dyn PriorityFeeProvider> = match priofee_provider {
+        PriorityFeeProviderKind::Quicknode => Arc::new(QuickNodeProvider {
+            url: priofee_url,
+            n_blocks: opts.priofee_n_blocks,
+        }),
+        PriorityFeeProviderKind::Helius => Arc::new(HeliusProvider {
+            url: priofee_url,
+            n_blocks: opts.priofee_n_blocks,
+        }),
+        PriorityFeeProviderKind::Rpc => Arc::new(RpcProvider {
+            rpc_client: Arc::clone(&rpc_client),
+            n_blocks: opts.priofee_n_blocks,
+        }),
+    };
+    let (priofees_handle, priofees_task) = start_priofees_task(
+        priofee_provider,
+        opts.amm_program_id,
+        accounts_service.clone(),
+        opts.priofee_poll_frequency_secs.map(Duration::from_secs),
+    )
+    .await;
+    tasks.push(priofees_task);
+
+    let (pool_activity, pool_activity_task) =
+        gfx_swap::slippage::start_pool_activity_task(accounts_service.clone());
+    tasks.push(pool_activity_task);
+
+    let mut quote_verification = QuoteVerification::default();
+    if let Some(level) = opts.quote_verification {
+        quote_verification.level = level;
+    }
+    if let Some(max_deviation_bps) = opts.max_quote_deviation_bps {
+        quote_verification.max_deviation_bps = max_deviation_bps;
+    }
+    let sponsor = opts
+        .sponsor_keypair
+        .as_deref()
+        .map(|path| Sponsor::from_file(path, opts.sponsor_max_lamports_per_swap))
+        .transpose()?;
+    if let Some(sponsor) = &sponsor {
+        log::info!(
+            "Sponsoring swaps from {}, up to {} lamports each",
+            sponsor.pubkey(),
+            sponsor.max_lamports_per_swap
+        );
+    }
+    let (jito, jito_task) = jito::start_jito_task(
+        opts.jito_block_engine_url
+            .as_deref()
+            .unwrap_or(jito::DEFAULT_BLOCK_ENGINE_URL),
+        opts.jito_tip_floor_url
+            .unwrap_or_else(|| jito::DEFAULT_TIP_FLOOR_URL.to_string()),
+    );
+    tasks.push(jito_task);
     let gfx_swap = GfxSwapClient {
         solana_rpc: Arc::clone(&rpc_client),
         accounts_service,
         gamma_config: opts.amm_config,
         gamma_program_id: opts.amm_program_id,
         blockhash,
-        priofees_handle,
+        priofees_handle: Some(priofees_handle),
         referral: opts.referral_account,
         referral_program: opts.referral_program,
+        referral_project: opts.referral_project,
+        allowed_referrals: opts
+            .allowed_referral_accounts
+            .unwrap_or_default()
+            .into_iter()
+            .collect(),
+        token_policy: opts
+            .allowed_token_extensions
+            .map(TokenExtensionPolicy::new)
+            .unwrap_or_default(),
+        quote_verification,
+        quote_cache: QuoteCache::new(
+            opts.quote_signing_key,
+            opts.quote_ttl_slots,
+            opts.quote_cache_size,
+        ),
+        pool_activity,
+        sponsor,
+        jito,
+        preflight_checks: opts.preflight_checks,
+        recent_clock: Default::default(),
+        recent_price_index: Default::default(),
     };
     let socket_addr = format!("{}:{}", opts.host, opts.port).parse::<SocketAddr>()?;
 
     let app = Router::new()
         .route("/quote", get(handlers::quote::quote))
+        .route("/quote/ladder", get(handlers::quote::quote_ladder))
+        .route("/quotes", post(handlers::quote::quote_batch))
+        .route("/ws/quotes", get(handlers::quote_stream::quote_stream))
+        .route("/pools", get(handlers::pools::pools))
+        .route("/pools/:pool/twap", get(handlers::pools::twap))
+        .route("/price", get(handlers::price::price))
+        .route("/priority-fees", get(handlers::network::priority_fees))
+        .route("/blockhash", get(handlers::network::blockhash))
         .route("/swap", post(handlers::swap::swap_transaction))
+        .route("/execute", post(handlers::execute::execute))
+        .route("/liquidity/deposit", post(handlers::liquidity::deposit))
+        .route("/liquidity/withdraw", post(handlers::liquidity::withdraw))
+        .route(
+            "/referral/initialize-account",
+            post(handlers::referral::initialize_account),
+        )
+        .route(
+            "/referral/initialize-token-accounts",
+            post(handlers::referral::initialize_token_accounts),
+        )
+        .route("/referral/:account/fees", get(handlers::referral::fees))
         .route(
             "/swap-instructions",
             post(handlers::swap::swap_instructions),
@@ -1,130 +1,374 @@
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use dashmap::DashMap;
+use futures::StreamExt;
 use log::error;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
 use tokio::sync::RwLock;
 
-// https://marketplace.quicknode.com/add-on/solana-priority-fee
+use crate::accounts::service::AccountsService;
+
+pub mod helius;
+pub mod quicknode;
+pub mod rpc;
 
 const DEFAULT_N_BLOCKS: u16 = 100;
 const DEFAULT_DURATION: Duration = Duration::from_secs(1);
+/// Compute units assumed when converting a per-compute-unit estimate to a per-transaction one
+const ESTIMATE_COMPUTE_UNITS: u64 = 200_000;
+const MICRO_LAMPORTS_PER_LAMPORT: u64 = 1_000_000;
+/// Most accounts a transaction can lock, and so the most the fee RPC methods consider
+const MAX_FEE_ACCOUNTS: usize = 128;
+/// Pools nobody has swapped through in this long are forgotten
+const POOL_FEES_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+/// Pools are only re-estimated while they were asked for this recently, and their estimates
+/// are only used for this long after being refreshed
+const POOL_FEES_ACTIVE_WINDOW: Duration = Duration::from_secs(60);
+/// How long a pool's estimate is used before it's re-estimated. Polling providers for every
+/// pool on every program-wide poll would cost a request per pool per second.
+const POOL_FEES_TTL: Duration = Duration::from_secs(10);
+/// Most pools estimated individually. Others get the program-wide estimate.
+const MAX_TRACKED_POOLS: usize = 256;
+/// Pool estimates requested from the provider at once
+const POOL_FEES_CONCURRENCY: usize = 8;
+/// Failed polls in a row after which the program-wide estimate is reported as stale
+const STALE_AFTER_FAILURES: u32 = 3;
+
+/// Where priority fee estimates come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PriorityFeeProviderKind {
+    /// QuickNode's `qn_estimatePriorityFees` add-on
+    Quicknode,
+    /// Helius-style `getPriorityFeeEstimate`
+    Helius,
+    /// The standard `getRecentPrioritizationFees` method, available on any RPC
+    Rpc,
+}
+
+/// A source of priority fee estimates
+#[async_trait]
+pub trait PriorityFeeProvider: Send + Sync {
+    /// Estimates fees for a transaction write-locking `accounts`
+    async fn estimate(&self, accounts: &[Pubkey]) -> anyhow::Result<PriorityFeeEstimate>;
+
+    fn name(&self) -> &'static str;
+}
 
 #[derive(Clone)]
 pub struct PrioFeesHandle {
-    latest: Arc<RwLock<QnPriofee>>,
+    latest: Arc<RwLock<LatestPriofee>>,
+    pools: Arc<DashMap<Pubkey, PoolFees>>,
+    source: &'static str,
+}
+
+/// The program-wide estimate and how its polling is going
+struct LatestPriofee {
+    estimate: PriorityFeeEstimate,
+    updated_at: Instant,
+    /// Polls that failed since the last successful one
+    consecutive_failures: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PriorityFeesReport {
+    /// Percentiles in microlamports per compute unit
+    pub per_compute_unit: Priority,
+    /// Percentiles in lamports per transaction
+    pub per_transaction: Priority,
+    /// Milliseconds since the estimate was refreshed
+    pub age_ms: u64,
+    /// The provider the estimate came from
+    pub source: &'static str,
+    pub consecutive_failures: u32,
+    /// Whether polling has been failing, so the estimate may no longer reflect the market
+    pub stale: bool,
+}
+
+/// Fee estimate for the writable accounts of one pool
+struct PoolFees {
+    accounts: Vec<Pubkey>,
+    /// The estimate and when it was made
+    estimate: Option<(Instant, PriorityFeeEstimate)>,
+    last_requested: Instant,
+}
+
+impl PoolFees {
+    /// Whether the pool is still being asked for and its estimate is missing or past its TTL
+    fn due_for_refresh(&self, now: Instant) -> bool {
+        if now.duration_since(self.last_requested) >= POOL_FEES_ACTIVE_WINDOW {
+            return false;
+        }
+        match self.estimate {
+            Some((updated_at, _)) => now.duration_since(updated_at) >= POOL_FEES_TTL,
+            None => true,
+        }
+    }
+
+    /// The estimate, unless it's been too long since it was refreshed
+    fn fresh_estimate(&self, now: Instant) -> Option<PriorityFeeEstimate> {
+        self.estimate
+            .filter(|(updated_at, _)| now.duration_since(*updated_at) < POOL_FEES_ACTIVE_WINDOW)
+            .map(|(_, estimate)| estimate)
+    }
 }
 
 impl PrioFeesHandle {
-    pub async fn get_latest_priofee(&self) -> QnPriofee {
-        *self.latest.read().await
+    pub async fn get_latest_priofee(&self) -> PriorityFeeEstimate {
+        self.latest.read().await.estimate
+    }
+
+    pub async fn report(&self) -> PriorityFeesReport {
+        let latest = self.latest.read().await;
+        PriorityFeesReport {
+            per_compute_unit: latest.estimate.per_compute_unit,
+            per_transaction: latest.estimate.per_transaction,
+            age_ms: u64::try_from(latest.updated_at.elapsed().as_millis()).unwrap_or(u64::MAX),
+            source: self.source,
+            consecutive_failures: latest.consecutive_failures,
+            stale: latest.consecutive_failures >= STALE_AFTER_FAILURES,
+        }
+    }
+
+    /// The estimate for transactions write-locking `writable_accounts` of `pool`. The pool is
+    /// refreshed in the background while it keeps being asked for. Until its first refresh, when
+    /// its estimate is out of date, and past `MAX_TRACKED_POOLS`, this is the program-wide
+    /// estimate.
+    pub async fn get_pool_priofee(
+        &self,
+        pool: &Pubkey,
+        writable_accounts: &[Pubkey],
+    ) -> PriorityFeeEstimate {
+        let estimate = match self.pools.get_mut(pool) {
+            Some(mut pool_fees) => {
+                let now = Instant::now();
+                pool_fees.last_requested = now;
+                pool_fees.fresh_estimate(now)
+            }
+            None => {
+                if self.pools.len() < MAX_TRACKED_POOLS {
+                    self.pools.insert(
+                        *pool,
+                        PoolFees {
+                            accounts: writable_accounts.to_vec(),
+                            estimate: None,
+                            last_requested: Instant::now(),
+                        },
+                    );
+                }
+                None
+            }
+        };
+        match estimate {
+            Some(estimate) => estimate,
+            None => self.get_latest_priofee().await,
+        }
+    }
+
+    /// Drops idle pools and re-estimates recently requested ones whose estimate is due
+    async fn refresh_pools(&self, provider: &dyn PriorityFeeProvider) {
+        self.pools
+            .retain(|_, pool_fees| pool_fees.last_requested.elapsed() < POOL_FEES_IDLE_TIMEOUT);
+        let now = Instant::now();
+        let pools: Vec<(Pubkey, Vec<Pubkey>)> = self
+            .pools
+            .iter()
+            .filter(|pool_fees| pool_fees.due_for_refresh(now))
+            .map(|pool_fees| (*pool_fees.key(), pool_fees.accounts.clone()))
+            .collect();
+        let mut estimates = futures::stream::iter(pools)
+            .map(|(pool, accounts)| async move { (pool, provider.estimate(&accounts).await) })
+            .buffer_unordered(POOL_FEES_CONCURRENCY);
+        while let Some((pool, estimate)) = estimates.next().await {
+            match estimate {
+                Ok(estimate) => {
+                    if let Some(mut pool_fees) = self.pools.get_mut(&pool) {
+                        pool_fees.estimate = Some((Instant::now(), estimate));
+                    }
+                }
+                Err(e) => error!("Priority fees for pool {}: {}", pool, e),
+            }
+        }
     }
 }
 
+/// Polls `provider` for estimates covering the program and the tracked pools, and for each pool
+/// being swapped through on its own
 pub async fn start_priofees_task(
-    url: String,
-    n_blocks: Option<u16>,
-    account: Option<String>,
+    provider: Arc<dyn PriorityFeeProvider>,
+    program_id: Pubkey,
+    accounts_service: AccountsService,
     poll_duration: Option<Duration>,
-) -> anyhow::Result<(PrioFeesHandle, tokio::task::JoinHandle<anyhow::Result<()>>)> {
-    let response = qn_priority_fee_request(&url, n_blocks, account.clone()).await?;
-    let latest = Arc::new(RwLock::new(response));
+) -> (PrioFeesHandle, tokio::task::JoinHandle<anyhow::Result<()>>) {
+    let fee_accounts = move || {
+        std::iter::once(program_id)
+            .chain(accounts_service.pools())
+            .take(MAX_FEE_ACCOUNTS)
+            .collect::<Vec<_>>()
+    };
+    // A provider that's down at startup is retried on every poll like any other failure. Until
+    // it answers, the zeroed estimate has auto fees fall back to the minimums.
+    let latest = match provider.estimate(&fee_accounts()).await {
+        Ok(estimate) => {
+            log::info!("Priority fees from {}", provider.name());
+            LatestPriofee {
+                estimate,
+                updated_at: Instant::now(),
+                consecutive_failures: 0,
+            }
+        }
+        Err(e) => {
+            log::warn!(
+                "Priority fees from {} unavailable, auto fees use minimums until it answers: {}",
+                provider.name(),
+                e
+            );
+            LatestPriofee {
+                estimate: PriorityFeeEstimate::default(),
+                updated_at: Instant::now(),
+                consecutive_failures: 1,
+            }
+        }
+    };
+    let latest = Arc::new(RwLock::new(latest));
     let mut interval = tokio::time::interval(poll_duration.unwrap_or(DEFAULT_DURATION));
+    let handle = PrioFeesHandle {
+        latest,
+        pools: Arc::default(),
+        source: provider.name(),
+    };
     let task = tokio::spawn({
-        let latest = Arc::clone(&latest);
-        let url = url.clone();
-        let account = account.clone();
+        let handle = handle.clone();
         async move {
             loop {
                 interval.tick().await;
-                match qn_priority_fee_request(&url, n_blocks, account.clone()).await {
+                match provider.estimate(&fee_accounts()).await {
                     Ok(response) => {
                         // log::debug!("{response:#?}");
-                        *latest.write().await = response;
+                        *handle.latest.write().await = LatestPriofee {
+                            estimate: response,
+                            updated_at: Instant::now(),
+                            consecutive_failures: 0,
+                        };
+                    }
+                    Err(e) => {
+                        handle.latest.write().await.consecutive_failures += 1;
+                        error!("{}", e)
                     }
-                    Err(e) => error!("{}", e),
                 }
+                handle.refresh_pools(provider.as_ref()).await;
             }
         }
     });
-    let handle = PrioFeesHandle { latest };
-    Ok((handle, task))
+    (handle, task)
 }
 
-pub async fn qn_priority_fee_request(
-    url: &str,
-    n_blocks: Option<u16>,
-    account: Option<String>,
-) -> anyhow::Result<QnPriofee> {
-    let last_n_blocks = n_blocks.unwrap_or(DEFAULT_N_BLOCKS);
-    let mut params = json!({
-        "last_n_blocks": last_n_blocks
-    });
-    if let Some(account) = account {
-        params
-            .as_object_mut()
-            .map(|m| m.insert("account".to_string(), json!(account)));
-    }
-    let response = reqwest::Client::new()
-        .post(url)
-        .header("Content-Type", "application/json")
-        .json(&json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "qn_estimatePriorityFees",
-            "params": params
-        }))
-        .send()
-        .await;
-
-    let mut json = match response {
-        Ok(response) => response.json::<serde_json::Value>().await?,
-        Err(err) => bail!("Priofee req send error: {err}"),
-    };
-
-    if let Some(result) = json.get_mut("result").map(|res| res.take()) {
-        Ok(serde_json::from_value(result)?)
-    } else if let Some(error) = json.get_mut("error").map(|err| err.take()) {
-        Err(anyhow!("qn_estimatePriorityFees error: {}", error))
-    } else {
-        Err(anyhow!("qn_estimatePriorityFees error: Invalid response"))
-    }
-}
-
-// #[derive(Clone, Deserialize, Debug)]
-// struct Error {
-//     #[allow(unused)]
-//     code: i32,
-//     message: String,
-//     data: String,
-// }
-
-#[derive(Copy, Clone, Deserialize, Debug)]
-pub struct QnPriofee {
-    #[allow(unused)]
-    context: Context,
+#[derive(Copy, Clone, Default, Serialize, Deserialize, Debug)]
+pub struct PriorityFeeEstimate {
     /// It provides estimates for priority fees (in microlamports) based on per-compute-unit metrics
     pub per_compute_unit: Priority,
     /// It provides estimates for priority fees (in lamports) based on per-transaction metrics
     pub per_transaction: Priority,
 }
 
-#[derive(Copy, Clone, Serialize, Deserialize, Debug)]
-struct Context {
-    pub slot: u64,
+impl PriorityFeeEstimate {
+    /// For providers that only estimate per compute unit. Per-transaction fees assume a
+    /// transaction using `ESTIMATE_COMPUTE_UNITS`.
+    fn from_per_compute_unit(per_compute_unit: Priority) -> Self {
+        let per_transaction = |cu_price: u64| {
+            cu_price.saturating_mul(ESTIMATE_COMPUTE_UNITS) / MICRO_LAMPORTS_PER_LAMPORT
+        };
+        PriorityFeeEstimate {
+            per_compute_unit,
+            per_transaction: Priority {
+                extreme: per_transaction(per_compute_unit.extreme),
+                high: per_transaction(per_compute_unit.high),
+                medium: per_transaction(per_compute_unit.medium),
+                low: per_transaction(per_compute_unit.low),
+            },
+        }
+    }
 }
 
-#[derive(Copy, Clone, Serialize, Deserialize, Debug)]
+/// Fee levels as the provider defines them. QuickNode and `rpc` use the 95th, 80th, 60th and
+/// 40th percentiles, Helius the 95th, 75th, 50th and 25th.
+#[derive(Copy, Clone, Default, Serialize, Deserialize, Debug)]
 pub struct Priority {
-    /// Fee estimate for 95th percentile
+    /// Fee estimate for the highest level
     pub extreme: u64,
-    /// Fee estimate for 80th percentile
     pub high: u64,
-    /// Fee estimate for 60th percentile
     pub medium: u64,
-    /// Fee estimate for 40th percentile
+    /// Fee estimate for the lowest level
     pub low: u64,
-    // percentiles:
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate(fee: u64) -> PriorityFeeEstimate {
+        PriorityFeeEstimate::from_per_compute_unit(Priority {
+            extreme: fee,
+            high: fee,
+            medium: fee,
+            low: fee,
+        })
+    }
+
+    #[test]
+    fn pools_are_refreshed_once_their_estimate_is_due() {
+        let start = Instant::now();
+        let pool_fees = PoolFees {
+            accounts: vec![],
+            estimate: None,
+            last_requested: start,
+        };
+        assert!(pool_fees.due_for_refresh(start));
+
+        let pool_fees = PoolFees {
+            estimate: Some((start, estimate(1))),
+            ..pool_fees
+        };
+        assert!(!pool_fees.due_for_refresh(start + POOL_FEES_TTL / 2));
+        assert!(pool_fees.due_for_refresh(start + POOL_FEES_TTL));
+    }
+
+    #[test]
+    fn pools_not_asked_for_recently_are_not_refreshed() {
+        let start = Instant::now();
+        let pool_fees = PoolFees {
+            accounts: vec![],
+            estimate: None,
+            last_requested: start,
+        };
+        assert!(!pool_fees.due_for_refresh(start + POOL_FEES_ACTIVE_WINDOW));
+    }
+
+    #[test]
+    fn outdated_pool_estimates_are_not_used() {
+        let start = Instant::now();
+        let pool_fees = PoolFees {
+            accounts: vec![],
+            estimate: Some((start, estimate(1_000))),
+            last_requested: start,
+        };
+        assert_eq!(
+            pool_fees
+                .fresh_estimate(start + POOL_FEES_TTL)
+                .map(|estimate| estimate.per_compute_unit.medium),
+            Some(1_000)
+        );
+        assert!(pool_fees
+            .fresh_estimate(start + POOL_FEES_ACTIVE_WINDOW)
+            .is_none());
+    }
+
+    #[test]
+    fn per_transaction_fees_assume_the_estimate_compute_units() {
+        let estimate = estimate(1_000_000);
+        assert_eq!(estimate.per_transaction.medium, ESTIMATE_COMPUTE_UNITS);
+    }
 }
@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+
+use super::{Priority, PriorityFeeEstimate, PriorityFeeProvider};
+use crate::utils::json_rpc_request;
+
+// https://docs.helius.dev/solana-apis/priority-fee-api
+
+/// Most slots `getPriorityFeeEstimate` looks back over
+const MAX_LOOKBACK_SLOTS: u16 = 150;
+
+pub struct HeliusProvider {
+    pub url: String,
+    pub n_blocks: Option<u16>,
+}
+
+#[async_trait]
+impl PriorityFeeProvider for HeliusProvider {
+    async fn estimate(&self, accounts: &[Pubkey]) -> anyhow::Result<PriorityFeeEstimate> {
+        let mut options = json!({ "includeAllPriorityFeeLevels": true });
+        if let Some(n_blocks) = self.n_blocks {
+            options.as_object_mut().map(|m| {
+                m.insert(
+                    "lookbackSlots".to_string(),
+                    json!(n_blocks.min(MAX_LOOKBACK_SLOTS)),
+                )
+            });
+        }
+        let account_keys: Vec<String> = accounts.iter().map(Pubkey::to_string).collect();
+        let response: HeliusPriofee = json_rpc_request(
+            &self.url,
+            "getPriorityFeeEstimate",
+            json!([{ "accountKeys": account_keys, "options": options }]),
+        )
+        .await?;
+        let levels = response.priority_fee_levels;
+        // Helius levels are the 25th, 50th, 75th and 95th percentiles, lower than QuickNode's
+        // and rpc's below `veryHigh`
+        Ok(PriorityFeeEstimate::from_per_compute_unit(Priority {
+            extreme: levels.very_high as u64,
+            high: levels.high as u64,
+            medium: levels.medium as u64,
+            low: levels.low as u64,
+        }))
+    }
+
+    fn name(&self) -> &'static str {
+        "helius"
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct HeliusPriofee {
+    priority_fee_levels: PriorityFeeLevels,
+}
+
+/// Fee estimates in microlamports per compute unit
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PriorityFeeLevels {
+    low: f64,
+    medium: f64,
+    high: f64,
+    very_high: f64,
+}
@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+
+use super::{PriorityFeeEstimate, PriorityFeeProvider, DEFAULT_N_BLOCKS};
+use crate::utils::json_rpc_request;
+
+// https://marketplace.quicknode.com/add-on/solana-priority-fee
+
+pub struct QuickNodeProvider {
+    pub url: String,
+    pub n_blocks: Option<u16>,
+}
+
+#[async_trait]
+impl PriorityFeeProvider for QuickNodeProvider {
+    /// The add-on takes a single account, so only the first is used
+    async fn estimate(&self, accounts: &[Pubkey]) -> anyhow::Result<PriorityFeeEstimate> {
+        let mut params = json!({
+            "last_n_blocks": self.n_blocks.unwrap_or(DEFAULT_N_BLOCKS)
+        });
+        if let Some(account) = accounts.first() {
+            params
+                .as_object_mut()
+                .map(|m| m.insert("account".to_string(), json!(account.to_string())));
+        }
+        let response: QnPriofee =
+            json_rpc_request(&self.url, "qn_estimatePriorityFees", params).await?;
+        Ok(PriorityFeeEstimate {
+            per_compute_unit: response.per_compute_unit,
+            per_transaction: response.per_transaction,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "quicknode"
+    }
+}
+
+// #[derive(Clone, Deserialize, Debug)]
+// struct Error {
+//     #[allow(unused)]
+//     code: i32,
+//     message: String,
+//     data: String,
+// }
+
+#[derive(Copy, Clone, Deserialize, Debug)]
+struct QnPriofee {
+    #[allow(unused)]
+    context: Context,
+    per_compute_unit: super::Priority,
+    per_transaction: super::Priority,
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize, Debug)]
+struct Context {
+    pub slot: u64,
+}
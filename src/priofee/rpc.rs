@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+
+use super::{Priority, PriorityFeeEstimate, PriorityFeeProvider, DEFAULT_N_BLOCKS};
+
+/// Estimates from the standard `getRecentPrioritizationFees` method. The node reports, per recent
+/// slot, the lowest fee that landed a transaction write-locking the given accounts, so passing
+/// the pools' writable accounts keeps the estimate to the contention that matters for swaps.
+pub struct RpcProvider {
+    pub rpc_client: Arc<RpcClient>,
+    pub n_blocks: Option<u16>,
+}
+
+#[async_trait]
+impl PriorityFeeProvider for RpcProvider {
+    async fn estimate(&self, accounts: &[Pubkey]) -> anyhow::Result<PriorityFeeEstimate> {
+        let mut fees = self
+            .rpc_client
+            .get_recent_prioritization_fees(accounts)
+            .await?;
+        // Nodes keep up to 150 slots. Only the most recent `n_blocks` count.
+        fees.sort_unstable_by_key(|fee| std::cmp::Reverse(fee.slot));
+        fees.truncate(usize::from(self.n_blocks.unwrap_or(DEFAULT_N_BLOCKS)));
+        let mut fees: Vec<u64> = fees.into_iter().map(|fee| fee.prioritization_fee).collect();
+        fees.sort_unstable();
+
+        Ok(PriorityFeeEstimate::from_per_compute_unit(Priority {
+            extreme: percentile(&fees, 95),
+            high: percentile(&fees, 80),
+            medium: percentile(&fees, 60),
+            low: percentile(&fees, 40),
+        }))
+    }
+
+    fn name(&self) -> &'static str {
+        "rpc"
+    }
+}
+
+/// Nearest-rank percentile of sorted `values`, zero if there are none
+fn percentile(values: &[u64], percentile: usize) -> u64 {
+    if values.is_empty() {
+        return 0;
+    }
+    values[(values.len() - 1) * percentile / 100]
+}
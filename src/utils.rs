@@ -1,7 +1,16 @@
 use crate::accounts::PoolSlice;
-use gamma::states::{OBSERVATION_SEED, POOL_SEED, POOL_VAULT_SEED};
+use anyhow::{anyhow, bail};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use gamma::states::{OBSERVATION_SEED, POOL_SEED, POOL_VAULT_SEED, USER_POOL_LIQUIDITY_SEED};
 use gamma::AUTH_SEED;
+use serde_json::json;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::message::{Message, VersionedMessage};
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::VersionedTransaction;
 
 pub fn derive_pool_pda(
     config: &Pubkey,
@@ -36,6 +45,21 @@ pub fn derive_authority_pda(program_id: &Pubkey) -> (Pubkey, u8) {
     Pubkey::find_program_address(&[AUTH_SEED.as_bytes()], program_id)
 }
 
+pub fn derive_user_pool_liquidity_pda(
+    pool: &Pubkey,
+    user: &Pubkey,
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            USER_POOL_LIQUIDITY_SEED.as_bytes(),
+            pool.as_ref(),
+            user.as_ref(),
+        ],
+        program_id,
+    )
+}
+
 pub fn get_keys_for_pool_exclusive(
     pool: &Pubkey,
     data: &PoolSlice,
@@ -49,3 +73,49 @@ pub fn get_keys_for_pool_exclusive(
         // derive_vault_pda(pool, &data.token_1_mint, program_id).0,
     ]
 }
+
+/// Sends a JSON-RPC request and returns its `result`
+pub async fn json_rpc_request<T: serde::de::DeserializeOwned>(
+    url: &str,
+    method: &str,
+    params: serde_json::Value,
+) -> anyhow::Result<T> {
+    let response = reqwest::Client::new()
+        .post(url)
+        .header("Content-Type", "application/json")
+        .json(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params
+        }))
+        .send()
+        .await;
+
+    let mut json = match response {
+        Ok(response) => response.json::<serde_json::Value>().await?,
+        Err(err) => bail!("{method} request error: {err}"),
+    };
+
+    if let Some(result) = json.get_mut("result").map(|res| res.take()) {
+        Ok(serde_json::from_value(result)?)
+    } else if let Some(error) = json.get_mut("error").map(|err| err.take()) {
+        Err(anyhow!("{method} error: {}", error))
+    } else {
+        Err(anyhow!("{method} error: Invalid response"))
+    }
+}
+
+/// An unsigned transaction with room for its signatures, base64-encoded for clients to sign
+pub fn encode_transaction(
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    blockhash: Hash,
+) -> Result<String, bincode::Error> {
+    let message = Message::new_with_blockhash(instructions, Some(payer), &blockhash);
+    let transaction = VersionedTransaction {
+        signatures: vec![Signature::default(); usize::from(message.header.num_required_signatures)],
+        message: VersionedMessage::Legacy(message),
+    };
+    Ok(STANDARD.encode(bincode::serialize(&transaction)?))
+}
@@ -0,0 +1,166 @@
+//! Runs the Jito client and `/execute` against the mock block engine binary
+
+use std::net::TcpListener;
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use gamma_swap_api::gfx_swap::execute::{execute_bundle, ExecuteError, ExecuteRequest};
+use gamma_swap_api::jito::{start_jito_task, JitoClient, MAINNET_TIP_ACCOUNTS, MIN_TIP_LAMPORTS};
+use sha2::{Digest, Sha256};
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::message::{Message, VersionedMessage};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::VersionedTransaction;
+
+const TIP_FLOOR_LAMPORTS: u64 = 25_000;
+
+/// The mock block engine process, killed when dropped
+struct MockBlockEngine {
+    process: Child,
+    url: String,
+}
+
+impl Drop for MockBlockEngine {
+    fn drop(&mut self) {
+        self.process.kill().ok();
+        self.process.wait().ok();
+    }
+}
+
+/// Starts the mock block engine on a free port, and a Jito client once it has fetched the tip
+/// accounts and the tip floor from it
+async fn mock_block_engine() -> (MockBlockEngine, JitoClient) {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let process = Command::new(env!("CARGO_BIN_EXE_mock_block_engine"))
+        .env("MOCK_BLOCK_ENGINE_PORT", port.to_string())
+        .env("MOCK_TIP_FLOOR_LAMPORTS", TIP_FLOOR_LAMPORTS.to_string())
+        .spawn()
+        .unwrap();
+    let engine = MockBlockEngine {
+        process,
+        url: format!("http://127.0.0.1:{}", port),
+    };
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    loop {
+        // A fresh client polls right away, and retries every 10 seconds after a failure
+        let (jito, task) = start_jito_task(
+            &engine.url,
+            format!("{}/api/v1/bundles/tip_floor", engine.url),
+        );
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        if jito.random_tip_account().await.is_some() && jito.auto_tip_lamports().await.is_some() {
+            return (engine, jito);
+        }
+        task.abort();
+        assert!(
+            Instant::now() < deadline,
+            "Mock block engine didn't come up"
+        );
+    }
+}
+
+/// A signed transaction calling `program_id`, tipping `tip` if given
+fn transaction(program_id: &Pubkey, tip: Option<(Pubkey, u64)>) -> VersionedTransaction {
+    let payer = Keypair::new();
+    let mut instructions = vec![Instruction::new_with_bytes(*program_id, &[0], vec![])];
+    if let Some((tip_account, lamports)) = tip {
+        instructions.push(solana_sdk::system_instruction::transfer(
+            &payer.pubkey(),
+            &tip_account,
+            lamports,
+        ));
+    }
+    let message =
+        Message::new_with_blockhash(&instructions, Some(&payer.pubkey()), &Hash::default());
+    VersionedTransaction::try_new(VersionedMessage::Legacy(message), &[&payer]).unwrap()
+}
+
+fn request(transaction: &VersionedTransaction) -> ExecuteRequest {
+    ExecuteRequest {
+        signed_transaction: STANDARD.encode(bincode::serialize(transaction).unwrap()),
+    }
+}
+
+#[tokio::test]
+async fn fetches_tip_accounts_and_auto_tip() {
+    let (_engine, jito) = mock_block_engine().await;
+    let tip_account = jito.random_tip_account().await.unwrap();
+    assert!(MAINNET_TIP_ACCOUNTS.contains(&tip_account));
+    assert!(jito.is_tip_account(&tip_account).await);
+    assert!(!jito.is_tip_account(&Pubkey::new_unique()).await);
+    assert_eq!(jito.auto_tip_lamports().await, Some(TIP_FLOOR_LAMPORTS));
+}
+
+#[tokio::test]
+async fn custom_block_engines_have_no_fallback_tip_accounts() {
+    let (jito, task) = start_jito_task(
+        "http://127.0.0.1:1",
+        "http://127.0.0.1:1/api/v1/bundles/tip_floor".to_string(),
+    );
+    assert!(jito.random_tip_account().await.is_none());
+    task.abort();
+}
+
+#[tokio::test]
+async fn submits_tipped_gamma_transactions_as_bundles() {
+    let (_engine, jito) = mock_block_engine().await;
+    let program_id = Pubkey::new_unique();
+    let tip_account = jito.random_tip_account().await.unwrap();
+    let transaction = transaction(&program_id, Some((tip_account, MIN_TIP_LAMPORTS)));
+
+    let response = execute_bundle(&jito, &program_id, request(&transaction))
+        .await
+        .unwrap();
+    assert_eq!(response.signature, transaction.signatures[0].to_string());
+    // The mock's bundle IDs hash the bundled signatures
+    let bundle_id = hex::encode(Sha256::digest(transaction.signatures[0].as_ref()));
+    assert_eq!(response.bundle_id, bundle_id);
+}
+
+#[tokio::test]
+async fn rejects_transactions_without_a_tip() {
+    let (_engine, jito) = mock_block_engine().await;
+    let program_id = Pubkey::new_unique();
+    let tip_account = jito.random_tip_account().await.unwrap();
+
+    for tip in [
+        None,
+        Some((Pubkey::new_unique(), MIN_TIP_LAMPORTS)),
+        Some((tip_account, MIN_TIP_LAMPORTS - 1)),
+    ] {
+        let result =
+            execute_bundle(&jito, &program_id, request(&transaction(&program_id, tip))).await;
+        assert!(matches!(result, Err(ExecuteError::MissingTip)));
+    }
+}
+
+#[tokio::test]
+async fn rejects_transactions_that_dont_call_gamma() {
+    let (_engine, jito) = mock_block_engine().await;
+    let tip_account = jito.random_tip_account().await.unwrap();
+    let transaction = transaction(&Pubkey::new_unique(), Some((tip_account, MIN_TIP_LAMPORTS)));
+
+    let result = execute_bundle(&jito, &Pubkey::new_unique(), request(&transaction)).await;
+    assert!(matches!(result, Err(ExecuteError::NotAGammaTransaction(_))));
+}
+
+#[tokio::test]
+async fn rejects_unsigned_transactions() {
+    let (_engine, jito) = mock_block_engine().await;
+    let program_id = Pubkey::new_unique();
+    let tip_account = jito.random_tip_account().await.unwrap();
+    let mut transaction = transaction(&program_id, Some((tip_account, MIN_TIP_LAMPORTS)));
+    transaction.signatures[0] = Default::default();
+
+    let result = execute_bundle(&jito, &program_id, request(&transaction)).await;
+    assert!(matches!(result, Err(ExecuteError::InvalidTransaction(_))));
+}